@@ -1,3 +1,10 @@
+use itertools::Itertools;
+use num::BigUint;
+use std::collections::HashMap;
+
+/// The sum the expense report entries should add up to
+pub const TARGET: u32 = 2020;
+
 #[aoc_generator(day1)]
 pub fn input_generator(input: &str) -> Vec<u32> {
     input.lines().map(|x| x.trim().parse().unwrap()).collect()
@@ -5,26 +12,442 @@ pub fn input_generator(input: &str) -> Vec<u32> {
 
 #[aoc(day1, part1)]
 pub fn part1(input: &[u32]) -> u32 {
-    for x in input {
-        for y in input {
-            if x + y == 2020 {
-                return x * y;
+    product(input, &k_sum(input, 2, TARGET).unwrap())
+}
+
+#[aoc(day1, part2)]
+pub fn part2(input: &[u32]) -> u32 {
+    product(input, &k_sum(input, 3, TARGET).unwrap())
+}
+
+/// Product of the values at `indices`
+pub fn product(values: &[u32], indices: &[usize]) -> u32 {
+    indices.iter().map(|&i| values[i]).product()
+}
+
+/// Indices of `k` distinct entries summing to `target`, in ascending order.
+///
+/// Pairs are found with a single pass over a hash map. Larger `k` fix the
+/// smallest entries in sorted order and finish with a two-pointer scan.
+pub fn k_sum(values: &[u32], k: usize, target: u32) -> Option<Vec<usize>> {
+    let target = target as u64;
+    let mut indices = match k {
+        0 if target == 0 => Some(Vec::new()),
+        0 => None,
+        1 => values
+            .iter()
+            .position(|&v| v as u64 == target)
+            .map(|i| vec![i]),
+        2 => pair_sum(values, target),
+        _ => {
+            let mut order: Vec<usize> = (0..values.len()).collect();
+            order.sort_unstable_by_key(|&i| values[i]);
+            let mut chosen = Vec::with_capacity(k);
+            if sorted_sum(values, &order, k, target, &mut chosen) {
+                Some(chosen)
+            } else {
+                None
             }
         }
+    }?;
+    indices.sort_unstable();
+    Some(indices)
+}
+
+fn pair_sum(values: &[u32], target: u64) -> Option<Vec<usize>> {
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    for (i, &v) in values.iter().enumerate() {
+        let v = v as u64;
+        if v > target {
+            continue;
+        }
+        if let Some(&j) = seen.get(&(target - v)) {
+            return Some(vec![j, i]);
+        }
+        seen.entry(v).or_insert(i);
     }
-    unreachable!()
+    None
 }
 
-#[aoc(day1, part2)]
-pub fn part2(input: &[u32]) -> u32 {
-    for x in input {
-        for y in input {
-            for z in input {
-                if x + y + z == 2020 {
-                    return x * y * z;
+// Picks `k` entries from `order`, which is sorted by value
+fn sorted_sum(
+    values: &[u32],
+    order: &[usize],
+    k: usize,
+    target: u64,
+    chosen: &mut Vec<usize>,
+) -> bool {
+    let value = |i: usize| values[order[i]] as u64;
+    if k == 2 {
+        if order.len() < 2 {
+            return false;
+        }
+        let (mut low, mut high) = (0, order.len() - 1);
+        while low < high {
+            let sum = value(low) + value(high);
+            if sum == target {
+                chosen.extend([order[low], order[high]]);
+                return true;
+            } else if sum < target {
+                low += 1;
+            } else {
+                high -= 1;
+            }
+        }
+        return false;
+    }
+    for i in 0..order.len() {
+        // The remaining entries are at least as large
+        if value(i) * k as u64 > target {
+            break;
+        }
+        chosen.push(order[i]);
+        if sorted_sum(values, &order[i + 1..], k - 1, target - value(i), chosen) {
+            return true;
+        }
+        chosen.pop();
+    }
+    false
+}
+
+/// A combination of values summing to the target
+#[derive(Clone, Debug, PartialEq)]
+pub struct KSumSolution {
+    /// In ascending order, repeated values are used once per repetition
+    pub values: Vec<u32>,
+    /// Number of distinct index tuples with these values, which can be huge
+    /// with many repeated entries
+    pub count: BigUint,
+}
+
+impl KSumSolution {
+    /// Every index tuple in `values` with the solution's values, each in
+    /// ascending order
+    pub fn indices(&self, values: &[u32]) -> Vec<Vec<usize>> {
+        if self.values.is_empty() {
+            return vec![vec![]];
+        }
+        let mut tuples: Vec<Vec<usize>> = runs(&self.values)
+            .into_iter()
+            .map(|(m, v)| {
+                let positions: Vec<usize> = (0..values.len()).filter(|&i| values[i] == v).collect();
+                positions
+                    .into_iter()
+                    .combinations(m)
+                    .collect::<Vec<Vec<usize>>>()
+            })
+            .multi_cartesian_product()
+            .map(|parts| parts.into_iter().flatten().sorted().collect())
+            .collect();
+        tuples.sort_unstable();
+        tuples
+    }
+}
+
+/// Lazily enumerates every combination of `k` distinct entries summing to the
+/// target, see `k_sums`
+pub struct KSums {
+    /// `(value, count)` sorted by value
+    distinct: Vec<(u32, usize)>,
+    k: usize,
+    target: u64,
+    /// Indices into `distinct` for all but the last two values, ascending
+    prefix: Vec<usize>,
+    sum: u64,
+    /// Next candidate at each depth of `prefix`
+    next: Vec<usize>,
+    buffer: Vec<KSumSolution>,
+    done: bool,
+}
+
+/// Every combination of `k` distinct entries summing to `target`, grouped by
+/// their values.
+///
+/// Equal entries are counted instead of enumerated, so only distinct values
+/// are searched. All but the last two values are picked in ascending order and
+/// the last two are found with a two-pointer scan.
+pub fn k_sums(values: &[u32], k: usize, target: u32) -> KSums {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let distinct = runs(&sorted)
+        .into_iter()
+        .map(|(count, v)| (v, count))
+        .collect();
+    KSums {
+        distinct,
+        k,
+        target: target as u64,
+        prefix: Vec::with_capacity(k),
+        sum: 0,
+        next: vec![0],
+        buffer: Vec::new(),
+        done: false,
+    }
+}
+
+/// Number of index tuples of `k` distinct entries summing to `target`
+pub fn count_k_sums(values: &[u32], k: usize, target: u32) -> BigUint {
+    k_sums(values, k, target)
+        .map(|solution| solution.count)
+        .sum()
+}
+
+impl KSums {
+    fn value(&self, i: usize) -> u64 {
+        self.distinct[i].0 as u64
+    }
+
+    // Entries with the value at `i` not used by the prefix
+    fn available(&self, i: usize) -> usize {
+        self.distinct[i].1 - self.prefix.iter().filter(|&&p| p == i).count()
+    }
+
+    fn backtrack(&mut self) {
+        match self.prefix.pop() {
+            Some(i) => {
+                self.sum -= self.value(i);
+                self.next.pop();
+            }
+            None => self.done = true,
+        }
+    }
+
+    fn push(&mut self, last: &[usize]) {
+        let mut chosen: Vec<usize> = self.prefix.clone();
+        chosen.extend(last);
+        let count = runs(&chosen)
+            .into_iter()
+            .map(|(m, i)| binomial(self.distinct[i].1, m))
+            .product();
+        self.buffer.push(KSumSolution {
+            values: chosen.iter().map(|&i| self.distinct[i].0).collect(),
+            count,
+        });
+    }
+
+    // Completes the prefix with the remaining zero, one or two values
+    fn complete(&mut self) {
+        let rest = self.k - self.prefix.len();
+        let target = self.target - self.sum;
+        let start = self.prefix.last().copied().unwrap_or(0);
+        let end = self.distinct.len();
+        match rest {
+            0 if target == 0 => self.push(&[]),
+            1 => {
+                let found =
+                    self.distinct[start..].binary_search_by_key(&target, |&(v, _)| v as u64);
+                if let Ok(i) = found {
+                    if self.available(start + i) > 0 {
+                        self.push(&[start + i]);
+                    }
                 }
             }
+            2 if start < end => {
+                let (mut low, mut high) = (start, end - 1);
+                while low <= high {
+                    let sum = self.value(low) + self.value(high);
+                    if sum < target {
+                        low += 1;
+                        continue;
+                    }
+                    if sum == target {
+                        let enough = if low == high {
+                            self.available(low) >= 2
+                        } else {
+                            self.available(low) > 0 && self.available(high) > 0
+                        };
+                        if enough {
+                            self.push(&[low, high]);
+                        }
+                        low += 1;
+                    }
+                    if high == 0 {
+                        break;
+                    }
+                    high -= 1;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Iterator for KSums {
+    type Item = KSumSolution;
+
+    fn next(&mut self) -> Option<KSumSolution> {
+        let depth = self.k.saturating_sub(2);
+        while self.buffer.is_empty() && !self.done {
+            let current = self.prefix.len();
+            if current == depth {
+                self.complete();
+                // Emit in ascending order
+                self.buffer.reverse();
+                self.backtrack();
+                continue;
+            }
+            let candidate = self.next[current];
+            // The remaining values are at least as large as the candidate
+            if candidate >= self.distinct.len()
+                || self.sum + self.value(candidate) * (self.k - current) as u64 > self.target
+            {
+                self.backtrack();
+                continue;
+            }
+            self.next[current] += 1;
+            if self.available(candidate) > 0 {
+                self.prefix.push(candidate);
+                self.sum += self.value(candidate);
+                self.next.push(candidate);
+            }
+        }
+        self.buffer.pop()
+    }
+}
+
+// Runs of equal items as `(length, item)`
+fn runs<T: PartialEq + Copy>(items: &[T]) -> Vec<(usize, T)> {
+    let mut runs: Vec<(usize, T)> = Vec::new();
+    for &item in items {
+        match runs.last_mut() {
+            Some((count, last)) if *last == item => *count += 1,
+            _ => runs.push((1, item)),
+        }
+    }
+    runs
+}
+
+fn binomial(n: usize, k: usize) -> BigUint {
+    // Each partial product is itself a binomial, so the division is exact
+    (0..k).fold(BigUint::from(1u32), |acc, i| acc * (n - i) / (i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    static SAMPLE_INPUT: &'static str = "1721
+979
+366
+299
+675
+1456";
+
+    #[test]
+    fn test_part1() {
+        let input = input_generator(SAMPLE_INPUT);
+        assert_eq!(k_sum(&input, 2, TARGET), Some(vec![0, 3]));
+        assert_eq!(part1(&input), 514579);
+    }
+
+    #[test]
+    fn test_part2() {
+        let input = input_generator(SAMPLE_INPUT);
+        assert_eq!(k_sum(&input, 3, TARGET), Some(vec![1, 2, 4]));
+        assert_eq!(part2(&input), 241861950);
+    }
+
+    #[test]
+    fn test_k_sum() {
+        // 1010 can't be used twice
+        assert_eq!(k_sum(&[1010, 5], 2, 2020), None);
+        assert_eq!(k_sum(&[1010, 5, 1010], 2, 2020), Some(vec![0, 2]));
+        assert_eq!(k_sum(&[3, 3, 3], 3, 9), Some(vec![0, 1, 2]));
+        assert_eq!(k_sum(&[3, 3], 3, 9), None);
+        assert_eq!(k_sum(&[1, 2, 3, 4, 5], 4, 14), Some(vec![1, 2, 3, 4]));
+        assert_eq!(k_sum(&[1, 2, 3], 1, 2), Some(vec![1]));
+        assert_eq!(k_sum(&[1, 2, 3], 0, 0), Some(vec![]));
+        assert_eq!(k_sum(&[u32::MAX, u32::MAX, 1], 2, u32::MAX), None);
+        assert_eq!(k_sum(&[], 2, 0), None);
+    }
+
+    #[test]
+    fn test_k_sums() {
+        let values = [1010, 5, 1010, 2015, 1010, 2020, 0];
+        let solutions: Vec<KSumSolution> = k_sums(&values, 2, 2020).collect();
+        assert_eq!(
+            solutions,
+            vec![
+                KSumSolution {
+                    values: vec![0, 2020],
+                    count: BigUint::from(1u32)
+                },
+                KSumSolution {
+                    values: vec![5, 2015],
+                    count: BigUint::from(1u32)
+                },
+                KSumSolution {
+                    values: vec![1010, 1010],
+                    count: BigUint::from(3u32)
+                },
+            ]
+        );
+        assert_eq!(
+            solutions[2].indices(&values),
+            vec![vec![0, 2], vec![0, 4], vec![2, 4]]
+        );
+        assert_eq!(count_k_sums(&values, 3, 2020), BigUint::from(4u32));
+        assert_eq!(count_k_sums(&values, 4, 2020), BigUint::from(0u32));
+        assert_eq!(count_k_sums(&values, 1, 2020), BigUint::from(1u32));
+        assert_eq!(count_k_sums(&values, 0, 0), BigUint::from(1u32));
+        assert_eq!(count_k_sums(&[], 2, 0), BigUint::from(0u32));
+
+        let sample = input_generator(SAMPLE_INPUT);
+        let solution = k_sums(&sample, 3, TARGET).next().unwrap();
+        assert_eq!(
+            solution.indices(&sample),
+            vec![k_sum(&sample, 3, TARGET).unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_k_sums_large() {
+        // 20000 entries with many repeated values
+        let values: Vec<u32> = (0..20000).map(|i| (i * 7919) % 2021).collect();
+        let solutions: Vec<KSumSolution> = k_sums(&values, 2, TARGET).collect();
+        // Every pair of residues, including 1010 twice
+        assert_eq!(solutions.len(), 1011);
+        assert!(solutions
+            .iter()
+            .all(|s| s.values.iter().sum::<u32>() == TARGET));
+    }
+
+    #[test]
+    fn test_k_sums_duplicated() {
+        // C(20000, 5) is larger than u64::MAX
+        let solutions: Vec<KSumSolution> = k_sums(&vec![0; 20000], 5, 0).collect();
+        assert_eq!(solutions.len(), 1);
+        assert_eq!(solutions[0].count.to_string(), "26653335666500004000");
+
+        let mut values = vec![1; 30000];
+        values.extend(vec![2; 30000]);
+        // Four of each, the product of the two counts overflows as well
+        assert_eq!(
+            count_k_sums(&values, 8, 12).to_string(),
+            "1138606948400175271400062556250000"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_k_sums_match_brute_force(
+            values in prop::collection::vec(0u32..20, 0..12),
+            k in 0usize..5,
+            target in 0u32..50,
+        ) {
+            let expected = (0..values.len())
+                .combinations(k)
+                .filter(|c| c.iter().map(|&i| values[i]).sum::<u32>() == target)
+                .count() as u64;
+            prop_assert_eq!(count_k_sums(&values, k, target), BigUint::from(expected));
+            let mut found: Vec<Vec<usize>> = k_sums(&values, k, target)
+                .flat_map(|s| s.indices(&values))
+                .collect();
+            found.sort_unstable();
+            prop_assert_eq!(found.len() as u64, expected);
+            found.dedup();
+            prop_assert_eq!(found.len() as u64, expected);
         }
     }
-    unreachable!()
 }
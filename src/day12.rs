@@ -1,6 +1,14 @@
+use std::fmt;
+
+pub mod planner;
+pub mod vector;
+
+use vector::{Heading, Metric, NavigationError, Vector};
+
 type Instructions = Vec<Instruction>;
+type Position = Vector<isize>;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Instruction {
     action: Action,
     value: usize,
@@ -21,6 +29,41 @@ impl std::str::FromStr for Instruction {
     }
 }
 
+impl Instruction {
+    pub fn new(action: Action, value: usize) -> Self {
+        Instruction { action, value }
+    }
+
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    pub fn value(&self) -> usize {
+        self.value
+    }
+
+    /// The value as a signed number of steps
+    pub fn steps(&self) -> Result<isize, NavigationError> {
+        use std::convert::TryFrom;
+        isize::try_from(self.value).map_err(|_| NavigationError::Overflow)
+    }
+
+    /// Signed rotation of a left/right instruction, clockwise is positive
+    pub fn degrees(&self) -> Result<isize, NavigationError> {
+        match self.action {
+            Action::Right => self.steps(),
+            Action::Left => Ok(-self.steps()?),
+            _ => unreachable!("Only right|left actions possible!"),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.action, self.value)
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Action {
     North,
@@ -49,92 +92,375 @@ impl std::str::FromStr for Action {
     }
 }
 
-static DIRECTION_ROTATION: [Action; 4] = [Action::East, Action::South, Action::West, Action::North];
-struct Ship {
-    direction: Action,
-    position: (isize, isize),
-    waypoint: (isize, isize),
-    move_type: MoveType,
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = match self {
+            Self::North => 'N',
+            Self::South => 'S',
+            Self::East => 'E',
+            Self::West => 'W',
+            Self::Left => 'L',
+            Self::Right => 'R',
+            Self::Forward => 'F',
+        };
+        write!(f, "{}", c)
+    }
+}
+
+/// The state of the ship after executing an instruction.
+///
+/// Positions use the puzzle's grid where east is positive x and north is
+/// negative y.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub instruction: Instruction,
+    pub position: Position,
+    pub heading: Heading,
+    pub waypoint: Position,
+}
+
+pub struct Ship {
+    heading: Heading,
+    position: Position,
+    waypoint: Position,
+    rotation: Rotation,
+    origin: Position,
+    path: Vec<Step>,
+    /// Unrounded position and waypoint, only kept up to date in
+    /// `Rotation::Exact`
+    exact_position: Vector<f64>,
+    exact_waypoint: Vector<f64>,
 }
 
 impl Default for Ship {
     fn default() -> Self {
         Ship {
-            direction: Action::East,
-            position: (0, 0),
-            waypoint: (10, -1),
-            move_type: MoveType::Direct,
+            heading: Heading::default(),
+            position: Vector::new(0, 0),
+            waypoint: Vector::new(10, -1),
+            rotation: Rotation::Grid,
+            origin: Vector::new(0, 0),
+            path: Vec::new(),
+            exact_position: Vector::new(0.0, 0.0),
+            exact_waypoint: Vector::new(10.0, -1.0),
         }
     }
 }
 
 impl Ship {
-    fn move_waypoint(&mut self, instruction: &Instruction) {
-        match instruction.action {
-            Action::East => self.waypoint.0 += instruction.value as isize,
-            Action::West => self.waypoint.0 -= instruction.value as isize,
-            Action::North => self.waypoint.1 -= instruction.value as isize,
-            Action::South => self.waypoint.1 += instruction.value as isize,
-            _ => (),
+    pub fn with_rotation(self, rotation: Rotation) -> Self {
+        Ship {
+            rotation,
+            exact_position: self.position.to_f64(),
+            exact_waypoint: self.waypoint.to_f64(),
+            ..self
+        }
+    }
+
+    /// Places the ship at `position`, which also becomes the route's origin
+    pub fn with_position(self, position: Position) -> Self {
+        Ship {
+            position,
+            origin: position,
+            exact_position: position.to_f64(),
+            ..self
+        }
+    }
+
+    pub fn with_heading(self, heading: Heading) -> Self {
+        Ship { heading, ..self }
+    }
+
+    pub fn with_waypoint(self, waypoint: Position) -> Self {
+        Ship {
+            waypoint,
+            exact_waypoint: waypoint.to_f64(),
+            ..self
+        }
+    }
+
+    /// Runs all instructions using `model`
+    pub fn run<M: MovementModel>(
+        mut self,
+        instructions: &[Instruction],
+        model: &mut M,
+    ) -> Result<Self, NavigationError> {
+        for ins in instructions {
+            self.execute(ins, model)?;
         }
+        Ok(self)
+    }
+
+    /// Executes a single instruction and records the resulting state
+    pub fn execute<M: MovementModel>(
+        &mut self,
+        ins: &Instruction,
+        model: &mut M,
+    ) -> Result<(), NavigationError> {
+        model.apply(self, ins)?;
+        self.path.push(Step {
+            instruction: ins.clone(),
+            position: self.position,
+            heading: self.heading,
+            waypoint: self.waypoint,
+        });
+        Ok(())
+    }
+
+    pub fn heading(&self) -> Heading {
+        self.heading
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
     }
 
-    fn move_ship(&mut self, direction: Action, steps: isize) {
-        match self.move_type {
-            MoveType::Direct => match direction {
-                Action::East => self.position.0 += steps,
-                Action::West => self.position.0 -= steps,
-                Action::North => self.position.1 -= steps,
-                Action::South => self.position.1 += steps,
-                _ => (),
-            },
-            MoveType::Waypoint => {
-                self.position.0 += steps * self.waypoint.0;
-                self.position.1 += steps * self.waypoint.1;
+    /// The waypoint, rounded to the closest grid point in `Rotation::Exact`
+    pub fn waypoint(&self) -> Position {
+        self.waypoint
+    }
+
+    /// The position, rounded to the closest grid point in `Rotation::Exact`
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// The unrounded position in `Rotation::Exact`, otherwise the position
+    pub fn exact_position(&self) -> Vector<f64> {
+        match self.rotation {
+            Rotation::Exact => self.exact_position,
+            _ => self.position.to_f64(),
+        }
+    }
+
+    /// The unrounded waypoint in `Rotation::Exact`, otherwise the waypoint
+    pub fn exact_waypoint(&self) -> Vector<f64> {
+        match self.rotation {
+            Rotation::Exact => self.exact_waypoint,
+            _ => self.waypoint.to_f64(),
+        }
+    }
+
+    /// Exact Manhattan distance between the origin and the current position
+    pub fn manhattan_distance(&self) -> Result<usize, NavigationError> {
+        self.position.checked_sub(self.origin)?.manhattan()
+    }
+
+    /// Distance between the origin and the current position
+    pub fn distance(&self, metric: Metric) -> Result<f64, NavigationError> {
+        metric.distance(self.origin, self.position)
+    }
+
+    /// Distance between the origin and the position after every step
+    pub fn distances(&self, metric: Metric) -> Result<Vec<f64>, NavigationError> {
+        self.path()
+            .map(|step| metric.distance(self.origin, step.position))
+            .collect()
+    }
+
+    /// Every recorded step, in the order the instructions were executed
+    pub fn path(&self) -> impl Iterator<Item = &Step> {
+        self.path.iter()
+    }
+
+    /// One line per step, preceded by a header line
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("step,instruction,x,y,heading,waypoint_x,waypoint_y\n");
+        for (i, step) in self.path().enumerate() {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                i + 1,
+                step.instruction,
+                step.position.x,
+                step.position.y,
+                step.heading,
+                step.waypoint.x,
+                step.waypoint.y
+            ));
+        }
+        csv
+    }
+
+    /// Plots the route from the origin as an SVG polyline. The start is marked
+    /// green and the final position red.
+    pub fn to_svg(&self) -> String {
+        let points: Vec<Position> = std::iter::once(self.origin)
+            .chain(self.path().map(|step| step.position))
+            .collect();
+        // Widened so that the extents can't overflow
+        let min_x = points.iter().map(|p| p.x as i128).min().unwrap();
+        let max_x = points.iter().map(|p| p.x as i128).max().unwrap();
+        let min_y = points.iter().map(|p| p.y as i128).min().unwrap();
+        let max_y = points.iter().map(|p| p.y as i128).max().unwrap();
+        // Pad the view so that the markers at the edges are visible
+        let pad = ((max_x - min_x).max(max_y - min_y) / 20).max(1);
+        let end = points[points.len() - 1];
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            min_x - pad,
+            min_y - pad,
+            max_x - min_x + 2 * pad,
+            max_y - min_y + 2 * pad
+        );
+        svg.push_str(&format!(
+            "  <polyline points=\"{}\" fill=\"none\" stroke=\"black\" vector-effect=\"non-scaling-stroke\"/>\n",
+            points
+                .iter()
+                .map(|p| format!("{},{}", p.x, p.y))
+                .collect::<Vec<String>>()
+                .join(" ")
+        ));
+        svg.push_str(&format!(
+            "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"green\"/>\n",
+            self.origin.x,
+            self.origin.y,
+            (pad / 2).max(1)
+        ));
+        svg.push_str(&format!(
+            "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"red\"/>\n",
+            end.x,
+            end.y,
+            (pad / 2).max(1)
+        ));
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Moves the ship by `delta`
+    pub fn translate(&mut self, delta: Position) -> Result<(), NavigationError> {
+        if let Rotation::Exact = self.rotation {
+            return self.translate_exact(delta.to_f64());
+        }
+        self.position = self.position.checked_add(delta)?;
+        Ok(())
+    }
+
+    fn translate_exact(&mut self, delta: Vector<f64>) -> Result<(), NavigationError> {
+        let position = self.exact_position + delta;
+        self.position = position.round()?;
+        self.exact_position = position;
+        Ok(())
+    }
+
+    /// Moves the ship `steps` towards `heading`. Headings that aren't on the
+    /// grid are only reachable with rounded or exact rotations. Rounded
+    /// rotations round the movement as well.
+    pub fn advance(&mut self, heading: Heading, steps: isize) -> Result<(), NavigationError> {
+        if let Rotation::Exact = self.rotation {
+            return self.translate_exact(heading.unit() * steps as f64);
+        }
+        let delta = match heading.grid_unit() {
+            Ok(unit) => unit.checked_mul(steps)?,
+            Err(_) => (heading.unit() * steps as f64).round()?,
+        };
+        self.translate(delta)
+    }
+
+    /// Moves the ship `steps` times the waypoint
+    pub fn follow_waypoint(&mut self, steps: isize) -> Result<(), NavigationError> {
+        match self.rotation {
+            Rotation::Exact => self.translate_exact(self.exact_waypoint * steps as f64),
+            _ => self.translate(self.waypoint.checked_mul(steps)?),
+        }
+    }
+
+    /// Moves the waypoint by `delta`
+    pub fn shift_waypoint(&mut self, delta: Position) -> Result<(), NavigationError> {
+        if let Rotation::Exact = self.rotation {
+            return self.set_exact_waypoint(self.exact_waypoint + delta.to_f64());
+        }
+        self.waypoint = self.waypoint.checked_add(delta)?;
+        Ok(())
+    }
+
+    fn set_exact_waypoint(&mut self, waypoint: Vector<f64>) -> Result<(), NavigationError> {
+        self.waypoint = waypoint.round()?;
+        self.exact_waypoint = waypoint;
+        Ok(())
+    }
+
+    /// Turns the ship's heading, respecting the rotation mode
+    pub fn turn(&mut self, degrees: isize) -> Result<(), NavigationError> {
+        if let Rotation::Grid = self.rotation {
+            if degrees % 90 != 0 {
+                return Err(NavigationError::NotQuarterTurn(degrees));
             }
         }
+        self.heading = self.heading.rotate(degrees);
+        Ok(())
     }
 
-    fn rotate(&mut self, instruction: &Instruction) {
-        match self.move_type {
-            MoveType::Direct => {
-                let index = DIRECTION_ROTATION
-                    .iter()
-                    .position(|x| *x == self.direction)
-                    .unwrap();
-                let steps = instruction.value as isize / 90;
-                let new_index = match instruction.action {
-                    Action::Right => index as isize + steps,
-                    Action::Left => index as isize - steps,
-                    _ => unreachable!("Only right|left actions possible!"),
-                };
-                let new_dir = DIRECTION_ROTATION[(((new_index % 4) + 4) % 4) as usize];
-                self.direction = new_dir;
+    /// Rotates the waypoint around the ship, respecting the rotation mode
+    pub fn rotate_waypoint(&mut self, degrees: isize) -> Result<(), NavigationError> {
+        self.waypoint = match self.rotation {
+            Rotation::Grid => self.waypoint.rotate(degrees)?,
+            Rotation::Rounded => self.waypoint.rotate_rounded(degrees)?,
+            Rotation::Exact => {
+                return self.set_exact_waypoint(self.exact_waypoint.rotate(degrees));
             }
-            MoveType::Waypoint => {
-                let steps = instruction.value as isize / 90;
-
-                let rotations = match (instruction.action, steps) {
-                    (Action::Right, 1) | (Action::Left, 3) => 1,
-                    (_, 2) => 2,
-                    (Action::Right, 3) | (Action::Left, 1) => 3,
-                    _ => 0,
-                };
-
-                let mut waypoint = self.waypoint.clone();
-                for _ in 0..rotations {
-                    waypoint = (-waypoint.1, waypoint.0);
-                }
-
-                self.waypoint = waypoint;
+        };
+        Ok(())
+    }
+}
+
+/// How rotations that aren't a multiple of 90 degrees are handled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotation {
+    /// Keep everything exactly on the grid, other angles are an error
+    Grid,
+    /// Allow any angle, positions are rounded to the closest grid point after
+    /// every move
+    Rounded,
+    /// Allow any angle, the position and waypoint are kept in floating point
+    /// and only rounded when read or measured
+    Exact,
+}
+
+/// Decides how each instruction moves the ship.
+///
+/// Models take `&mut self` so that they can keep state of their own in
+/// addition to what the `Ship` tracks.
+pub trait MovementModel {
+    fn apply(&mut self, ship: &mut Ship, instruction: &Instruction) -> Result<(), NavigationError>;
+}
+
+/// Part 1: N/S/E/W and F move the ship itself, L/R turn it
+pub struct DirectMovement;
+
+impl MovementModel for DirectMovement {
+    fn apply(&mut self, ship: &mut Ship, ins: &Instruction) -> Result<(), NavigationError> {
+        match ins.action {
+            Action::Left | Action::Right => ship.turn(ins.degrees()?),
+            Action::Forward => ship.advance(ship.heading, ins.steps()?),
+            dir => ship.advance(Heading::from_action(dir).unwrap(), ins.steps()?),
+        }
+    }
+}
+
+/// Part 2: N/S/E/W move the waypoint, L/R rotate it around the ship and F
+/// moves the ship towards the waypoint
+pub struct WaypointMovement;
+
+impl MovementModel for WaypointMovement {
+    fn apply(&mut self, ship: &mut Ship, ins: &Instruction) -> Result<(), NavigationError> {
+        match ins.action {
+            Action::Left | Action::Right => ship.rotate_waypoint(ins.degrees()?),
+            Action::Forward => ship.follow_waypoint(ins.steps()?),
+            dir => {
+                let unit = Heading::from_action(dir).unwrap().grid_unit().unwrap();
+                ship.shift_waypoint(unit.checked_mul(ins.steps()?)?)
             }
         }
     }
 }
 
-enum MoveType {
-    Direct,
-    Waypoint,
+/// Runs all instructions on a new ship using `model`
+pub fn navigate<M: MovementModel>(
+    instructions: &[Instruction],
+    model: &mut M,
+) -> Result<Ship, NavigationError> {
+    Ship::default().run(instructions, model)
 }
 
 #[aoc_generator(day12)]
@@ -144,33 +470,18 @@ pub fn input_generator(input: &str) -> Instructions {
 
 #[aoc(day12, part1)]
 pub fn part1(instructions: &Instructions) -> usize {
-    let mut ship = Ship::default();
-    for ins in instructions {
-        match ins.action {
-            Action::Forward => ship.move_ship(ship.direction, ins.value as isize),
-            Action::East | Action::West | Action::North | Action::South => {
-                ship.move_ship(ins.action, ins.value as isize)
-            }
-            Action::Left | Action::Right => ship.rotate(&ins),
-        }
-    }
-    (ship.position.0.abs() + ship.position.1.abs()) as usize
+    navigate(instructions, &mut DirectMovement)
+        .unwrap()
+        .manhattan_distance()
+        .unwrap()
 }
 
 #[aoc(day12, part2)]
 pub fn part2(instructions: &Instructions) -> usize {
-    let mut ship = Ship {
-        move_type: MoveType::Waypoint,
-        ..Ship::default()
-    };
-    for ins in instructions {
-        match ins.action {
-            Action::Forward => ship.move_ship(ins.action, ins.value as isize),
-            Action::East | Action::West | Action::North | Action::South => ship.move_waypoint(ins),
-            Action::Left | Action::Right => ship.rotate(&ins),
-        }
-    }
-    (ship.position.0.abs() + ship.position.1.abs()) as usize
+    navigate(instructions, &mut WaypointMovement)
+        .unwrap()
+        .manhattan_distance()
+        .unwrap()
 }
 
 #[cfg(test)]
@@ -237,79 +548,251 @@ F11";
     fn test_day12_ship_rotate() {
         // Rotate right (clockwise)
         let mut ship = Ship::default();
-        ship.rotate(&Instruction {
-            action: Action::Right,
-            value: 90,
-        });
-        assert_eq!(ship.direction, Action::South);
+        DirectMovement
+            .apply(&mut ship, &Instruction::new(Action::Right, 90))
+            .unwrap();
+        assert_eq!(ship.heading.to_action(), Some(Action::South));
 
         let mut ship = Ship::default();
-        ship.rotate(&Instruction {
-            action: Action::Right,
-            value: 270,
-        });
-        assert_eq!(ship.direction, Action::North);
+        DirectMovement
+            .apply(&mut ship, &Instruction::new(Action::Right, 270))
+            .unwrap();
+        assert_eq!(ship.heading.to_action(), Some(Action::North));
 
         // Rotate left (counter-clockwise)
         let mut ship = Ship::default();
-        ship.rotate(&Instruction {
-            action: Action::Left,
-            value: 90,
-        });
-        assert_eq!(ship.direction, Action::North);
+        DirectMovement
+            .apply(&mut ship, &Instruction::new(Action::Left, 90))
+            .unwrap();
+        assert_eq!(ship.heading.to_action(), Some(Action::North));
 
         let mut ship = Ship::default();
-        ship.rotate(&Instruction {
-            action: Action::Left,
-            value: 270,
-        });
-        assert_eq!(ship.direction, Action::South);
+        DirectMovement
+            .apply(&mut ship, &Instruction::new(Action::Left, 270))
+            .unwrap();
+        assert_eq!(ship.heading.to_action(), Some(Action::South));
     }
 
     #[test]
     fn test_day12_waypoint_rotate() {
         // Rotate right (clockwise)
-        let mut ship = Ship {
-            move_type: MoveType::Waypoint,
-            ..Ship::default()
-        };
-        assert_eq!(ship.waypoint, (10, -1));
+        let mut ship = Ship::default();
+        assert_eq!(ship.waypoint, Vector::new(10, -1));
 
-        ship.rotate(&Instruction {
-            action: Action::Right,
-            value: 90,
-        });
-        assert_eq!(ship.waypoint, (1, 10));
+        WaypointMovement
+            .apply(&mut ship, &Instruction::new(Action::Right, 90))
+            .unwrap();
+        assert_eq!(ship.waypoint, Vector::new(1, 10));
 
-        let mut ship = Ship {
-            move_type: MoveType::Waypoint,
-            ..Ship::default()
-        };
-        ship.rotate(&Instruction {
-            action: Action::Right,
-            value: 270,
-        });
-        assert_eq!(ship.waypoint, (-1, -10));
+        let mut ship = Ship::default();
+        WaypointMovement
+            .apply(&mut ship, &Instruction::new(Action::Right, 270))
+            .unwrap();
+        assert_eq!(ship.waypoint, Vector::new(-1, -10));
 
         // Rotate left (counter-clockwise)
-        let mut ship = Ship {
-            move_type: MoveType::Waypoint,
-            ..Ship::default()
-        };
-        ship.rotate(&Instruction {
-            action: Action::Left,
-            value: 90,
-        });
-        assert_eq!(ship.waypoint, (-1, -10));
+        let mut ship = Ship::default();
+        WaypointMovement
+            .apply(&mut ship, &Instruction::new(Action::Left, 90))
+            .unwrap();
+        assert_eq!(ship.waypoint, Vector::new(-1, -10));
 
-        let mut ship = Ship {
-            move_type: MoveType::Waypoint,
-            ..Ship::default()
-        };
-        ship.rotate(&Instruction {
-            action: Action::Left,
-            value: 270,
-        });
-        assert_eq!(ship.waypoint, (1, 10));
+        let mut ship = Ship::default();
+        WaypointMovement
+            .apply(&mut ship, &Instruction::new(Action::Left, 270))
+            .unwrap();
+        assert_eq!(ship.waypoint, Vector::new(1, 10));
+    }
+
+    #[test]
+    fn test_day12_path() {
+        let ship = navigate(&input_generator(SAMPLE_INPUT), &mut WaypointMovement).unwrap();
+        let positions: Vec<(isize, isize)> = ship
+            .path()
+            .map(|step| (step.position.x, step.position.y))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![(100, -10), (100, -10), (170, -38), (170, -38), (214, 72)]
+        );
+        let waypoints: Vec<(isize, isize)> = ship
+            .path()
+            .map(|step| (step.waypoint.x, step.waypoint.y))
+            .collect();
+        assert_eq!(
+            waypoints,
+            vec![(10, -1), (10, -4), (10, -4), (4, 10), (4, 10)]
+        );
+
+        let ship = navigate(&input_generator(SAMPLE_INPUT), &mut DirectMovement).unwrap();
+        let headings: Vec<isize> = ship.path().map(|step| step.heading.degrees()).collect();
+        assert_eq!(headings, vec![0, 0, 0, 90, 90]);
+        assert_eq!(ship.position(), Vector::new(17, 8));
+    }
+
+    #[test]
+    fn test_day12_export() {
+        let ship = navigate(&input_generator(SAMPLE_INPUT), &mut DirectMovement).unwrap();
+        assert_eq!(
+            ship.to_csv(),
+            "step,instruction,x,y,heading,waypoint_x,waypoint_y
+1,F10,10,0,0,10,-1
+2,N3,10,-3,0,10,-1
+3,F7,17,-3,0,10,-1
+4,R90,17,-3,90,10,-1
+5,F11,17,8,90,10,-1
+"
+        );
+        let svg = ship.to_svg();
+        assert!(
+            svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-1 -4 19 13\">")
+        );
+        assert!(svg.contains("points=\"0,0 10,0 10,-3 17,-3 17,-3 17,8\""));
+        assert!(svg.contains("<circle cx=\"17\" cy=\"8\" r=\"1\" fill=\"red\"/>"));
+    }
+
+    #[test]
+    fn test_day12_unsupported_angle() {
+        let instructions = input_generator("F10\nR45\nF10");
+        assert_eq!(
+            navigate(&instructions, &mut DirectMovement).err(),
+            Some(NavigationError::NotQuarterTurn(45))
+        );
+        let instructions = input_generator("F10\nL30\nF10");
+        assert_eq!(
+            navigate(&instructions, &mut WaypointMovement).err(),
+            Some(NavigationError::NotQuarterTurn(-30))
+        );
+    }
+
+    #[test]
+    fn test_day12_rounded_rotation() {
+        let instructions = input_generator("F10\nR45\nF10\nL135\nF1");
+        let ship = Ship::default()
+            .with_rotation(Rotation::Rounded)
+            .run(&instructions, &mut DirectMovement)
+            .unwrap();
+        assert_eq!(ship.position(), Vector::new(17, 6));
+        assert_eq!(ship.heading.to_action(), Some(Action::North));
+
+        let instructions = input_generator("R30\nF2");
+        let ship = Ship::default()
+            .with_rotation(Rotation::Rounded)
+            .run(&instructions, &mut WaypointMovement)
+            .unwrap();
+        assert_eq!(ship.waypoint, Vector::new(9, 4));
+        assert_eq!(ship.position(), Vector::new(18, 8));
+    }
+
+    #[test]
+    fn test_day12_exact_rotation() {
+        // A full circle in 30 degree steps
+        let instructions = input_generator(&"R30\nF1\n".repeat(12));
+        let ship = Ship::default()
+            .with_waypoint(Vector::new(3, 0))
+            .with_rotation(Rotation::Exact)
+            .run(&instructions, &mut WaypointMovement)
+            .unwrap();
+        assert_eq!(ship.waypoint(), Vector::new(3, 0));
+        assert_eq!(ship.position(), Vector::new(0, 0));
+        assert!((ship.exact_waypoint() - Vector::new(3.0, 0.0)).length() < 1e-9);
+        assert!(ship.exact_position().length() < 1e-9);
+        assert_eq!(ship.manhattan_distance(), Ok(0));
+        // Rounding after every step drifts off
+        let ship = Ship::default()
+            .with_waypoint(Vector::new(3, 0))
+            .with_rotation(Rotation::Rounded)
+            .run(&instructions, &mut WaypointMovement)
+            .unwrap();
+        assert_eq!(ship.waypoint(), Vector::new(3, -1));
+
+        let ship = Ship::default()
+            .with_rotation(Rotation::Exact)
+            .run(
+                &input_generator("F10\nR45\nF10\nL135\nF1"),
+                &mut DirectMovement,
+            )
+            .unwrap();
+        assert_eq!(ship.position(), Vector::new(17, 6));
+        assert!((ship.exact_position().x - (10.0 + 50f64.sqrt())).abs() < 1e-9);
+    }
+
+    /// Waypoint movement where the current pushes the ship south after every
+    /// forward move
+    struct Drift(isize);
+
+    impl MovementModel for Drift {
+        fn apply(&mut self, ship: &mut Ship, ins: &Instruction) -> Result<(), NavigationError> {
+            WaypointMovement.apply(ship, ins)?;
+            if ins.action() == Action::Forward {
+                ship.translate(Vector::new(0, self.0))?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_day12_custom_model() {
+        let ship = navigate(&input_generator(SAMPLE_INPUT), &mut Drift(1)).unwrap();
+        assert_eq!(ship.position(), Vector::new(214, 75));
+    }
+
+    #[test]
+    fn test_day12_overflow() {
+        let max = isize::MAX;
+        let instructions = input_generator(&format!("F{}\nF1", max));
+        assert_eq!(
+            navigate(&instructions, &mut DirectMovement).err(),
+            Some(NavigationError::Overflow)
+        );
+        let instructions = input_generator(&format!("F{}", max as usize + 1));
+        assert_eq!(
+            navigate(&instructions, &mut DirectMovement).err(),
+            Some(NavigationError::Overflow)
+        );
+        let instructions = input_generator(&format!("F{}", max / 5));
+        assert_eq!(
+            navigate(&instructions, &mut WaypointMovement).err(),
+            Some(NavigationError::Overflow)
+        );
+        let instructions = input_generator(&format!("E{}\nE{}", max, max));
+        assert_eq!(
+            navigate(&instructions, &mut WaypointMovement).err(),
+            Some(NavigationError::Overflow)
+        );
+        let instructions = input_generator(&format!("F{}\nS{}", max, max));
+        let ship = navigate(&instructions, &mut DirectMovement).unwrap();
+        assert_eq!(ship.manhattan_distance(), Ok(usize::MAX - 1));
+        assert_eq!(ship.distance(Metric::Chebyshev), Ok(max as f64));
+        // Further from the origin than an isize can represent
+        let ship = Ship::default()
+            .with_position(Vector::new(-1, 0))
+            .run(
+                &input_generator(&format!("F{}\nE1", max)),
+                &mut DirectMovement,
+            )
+            .unwrap();
+        assert_eq!(ship.manhattan_distance(), Err(NavigationError::Overflow));
+    }
+
+    #[test]
+    fn test_day12_metrics() {
+        let ship = navigate(&input_generator(SAMPLE_INPUT), &mut DirectMovement).unwrap();
+        assert_eq!(ship.manhattan_distance(), Ok(25));
+        assert_eq!(ship.distance(Metric::Manhattan), Ok(25.0));
+        assert_eq!(ship.distance(Metric::Chebyshev), Ok(17.0));
+        assert_eq!(ship.distance(Metric::Euclidean), Ok(353f64.sqrt()));
+        assert_eq!(
+            ship.distances(Metric::Chebyshev),
+            Ok(vec![10.0, 10.0, 17.0, 17.0, 17.0])
+        );
+
+        // Distances are relative to where the ship started
+        let ship = Ship::default()
+            .with_position(Vector::new(-17, -8))
+            .run(&input_generator(SAMPLE_INPUT), &mut DirectMovement)
+            .unwrap();
+        assert_eq!(ship.position(), Vector::new(0, 0));
+        assert_eq!(ship.manhattan_distance(), Ok(25));
     }
 }
@@ -0,0 +1,325 @@
+//! Generates instructions that take a ship to a target, the reverse of
+//! navigating.
+//!
+//! `plan_waypoint` only rotates by quarter turns, so its plans are valid in
+//! every rotation mode. `plan_direct` turns by whatever angle the target
+//! heading needs, which may be one that only `Rotation::Rounded` and
+//! `Rotation::Exact` allow.
+
+use super::vector::{Heading, NavigationError, Vector};
+use super::{Action, Instruction, Position, Ship};
+use num::Integer;
+
+/// Instruction sequence taking `ship` to `target`, facing `heading`, with
+/// `DirectMovement`.
+///
+/// The ship only moves along the axes, so the plan is the shortest one when
+/// both headings are cardinal: every instruction then changes either the x or
+/// the y position or the heading, and at most one of each is needed. With a
+/// diagonal heading a forward move may cover both axes at once, which isn't
+/// considered. Fails if the turn isn't possible with the ship's rotation mode,
+/// or with `NavigationError::Overflow` if a move doesn't fit in an instruction.
+pub fn plan_direct(
+    ship: &Ship,
+    target: Position,
+    heading: Heading,
+) -> Result<Vec<Instruction>, NavigationError> {
+    let turn = heading.degrees() - ship.heading().degrees();
+    // Validate the turn using the ship's own rules
+    Ship::default().with_rotation(ship.rotation()).turn(turn)?;
+
+    let mut plan = shift(target.checked_sub(ship.position())?);
+    plan.extend(rotation(turn));
+    replayable(plan)
+}
+
+/// Instruction sequence taking `ship` to `target` with `WaypointMovement`,
+/// using at most three instructions.
+///
+/// A single forward move is always enough once the waypoint points at the
+/// target. In order of cost the waypoint already points at the target, it does
+/// after a single rotation or shift, or it's shifted on both axes.
+///
+/// The plan is the shortest among plans that only rotate by quarter turns:
+/// only a forward move moves the ship, anything after the last one is wasted
+/// and two forward moves in a row can be merged, so every plan of one or two
+/// instructions is among the cases checked before falling back to three.
+/// Rotations by other angles aren't considered, they may give a shorter plan
+/// in the other rotation modes. The plan starts from `Ship::waypoint`, which
+/// is rounded in `Rotation::Exact`. Fails with `NavigationError::Overflow` if a
+/// move doesn't fit in an instruction.
+pub fn plan_waypoint(ship: &Ship, target: Position) -> Result<Vec<Instruction>, NavigationError> {
+    let delta = target.checked_sub(ship.position())?;
+    if delta == Vector::new(0, 0) {
+        return Ok(Vec::new());
+    }
+    let waypoint = ship.waypoint();
+    let forward = |n: usize| Instruction::new(Action::Forward, n);
+
+    for quarter in 0..4 {
+        if let Some(n) = waypoint
+            .rotate(quarter * 90)
+            .ok()
+            .and_then(|w| multiple(delta, w))
+        {
+            let mut plan: Vec<Instruction> = rotation(quarter * 90).into_iter().collect();
+            plan.push(forward(n));
+            return replayable(plan);
+        }
+    }
+
+    // Keep one axis of the waypoint, which fixes the number of steps
+    let shifted = [
+        multiple(Vector::new(0, delta.y), Vector::new(0, waypoint.y))
+            .map(|n| (n, Vector::new(delta.x / n as isize, waypoint.y))),
+        multiple(Vector::new(delta.x, 0), Vector::new(waypoint.x, 0))
+            .map(|n| (n, Vector::new(waypoint.x, delta.y / n as isize))),
+    ];
+    for (n, new_waypoint) in shifted.iter().flatten() {
+        if delta == new_waypoint.checked_mul(*n as isize)? {
+            let mut plan = shift(new_waypoint.checked_sub(waypoint)?);
+            plan.push(forward(*n));
+            return replayable(plan);
+        }
+    }
+
+    // The largest step count gives the smallest waypoint to shift to. Only a
+    // gcd of 2^63 is too large to be a step count, half of it always fits.
+    let mut n = delta.x.unsigned_abs().gcd(&delta.y.unsigned_abs());
+    if n > isize::MAX as usize {
+        n /= 2;
+    }
+    let new_waypoint = Vector::new(
+        (delta.x as i128 / n as i128) as isize,
+        (delta.y as i128 / n as i128) as isize,
+    );
+    let mut plan = shift(new_waypoint.checked_sub(waypoint)?);
+    plan.push(forward(n));
+    replayable(plan)
+}
+
+// The simulator only accepts values that fit in an `isize`
+fn replayable(plan: Vec<Instruction>) -> Result<Vec<Instruction>, NavigationError> {
+    for instruction in &plan {
+        instruction.steps()?;
+    }
+    Ok(plan)
+}
+
+// The positive `n` where `target == waypoint * n`, if there is one
+fn multiple(target: Position, waypoint: Position) -> Option<usize> {
+    let n = if waypoint.x != 0 {
+        if target.x.checked_rem(waypoint.x)? != 0 {
+            return None;
+        }
+        target.x.checked_div(waypoint.x)?
+    } else if waypoint.y != 0 {
+        if target.y.checked_rem(waypoint.y)? != 0 {
+            return None;
+        }
+        target.y.checked_div(waypoint.y)?
+    } else {
+        return None;
+    };
+    if n > 0 && waypoint.checked_mul(n).ok()? == target {
+        Some(n as usize)
+    } else {
+        None
+    }
+}
+
+fn shift(delta: Position) -> Vec<Instruction> {
+    let mut plan = Vec::new();
+    if delta.x != 0 {
+        let action = if delta.x > 0 {
+            Action::East
+        } else {
+            Action::West
+        };
+        plan.push(Instruction::new(action, delta.x.unsigned_abs()));
+    }
+    if delta.y != 0 {
+        let action = if delta.y > 0 {
+            Action::South
+        } else {
+            Action::North
+        };
+        plan.push(Instruction::new(action, delta.y.unsigned_abs()));
+    }
+    plan
+}
+
+// Turns the shortest way around, preferring right on 180
+fn rotation(degrees: isize) -> Option<Instruction> {
+    match degrees.rem_euclid(360) {
+        0 => None,
+        d if d <= 180 => Some(Instruction::new(Action::Right, d as usize)),
+        d => Some(Instruction::new(Action::Left, (360 - d) as usize)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{navigate, DirectMovement, Rotation, WaypointMovement};
+    use super::*;
+    use proptest::prelude::*;
+
+    fn start(x: isize, y: isize, heading: isize, wx: isize, wy: isize) -> Ship {
+        Ship::default()
+            .with_position(Vector::new(x, y))
+            .with_heading(Heading::new(heading))
+            .with_waypoint(Vector::new(wx, wy))
+    }
+
+    #[test]
+    fn test_plan_direct() {
+        let ship = Ship::default();
+        let plan = plan_direct(&ship, Vector::new(17, 8), Heading::new(90)).unwrap();
+        assert_eq!(
+            plan,
+            vec![
+                Instruction::new(Action::East, 17),
+                Instruction::new(Action::South, 8),
+                Instruction::new(Action::Right, 90),
+            ]
+        );
+        let plan = plan_direct(&ship, Vector::new(0, -3), Heading::new(270)).unwrap();
+        assert_eq!(
+            plan,
+            vec![
+                Instruction::new(Action::North, 3),
+                Instruction::new(Action::Left, 90),
+            ]
+        );
+        assert_eq!(
+            plan_direct(&ship, Vector::new(0, 0), Heading::new(0)),
+            Ok(vec![])
+        );
+        assert_eq!(
+            plan_direct(&ship, Vector::new(0, 0), Heading::new(45)),
+            Err(NavigationError::NotQuarterTurn(45))
+        );
+
+        let ship = Ship::default().with_rotation(Rotation::Rounded);
+        let plan = plan_direct(&ship, Vector::new(0, 0), Heading::new(315)).unwrap();
+        assert_eq!(plan, vec![Instruction::new(Action::Left, 45)]);
+
+        // Diagonal moves aren't planned, even though F10 would do here
+        let ship = ship.with_heading(Heading::new(45));
+        let plan = plan_direct(&ship, Vector::new(7, 7), Heading::new(45)).unwrap();
+        assert_eq!(
+            plan,
+            vec![
+                Instruction::new(Action::East, 7),
+                Instruction::new(Action::South, 7),
+            ]
+        );
+        let diagonal = Ship::default()
+            .with_rotation(Rotation::Rounded)
+            .with_heading(Heading::new(45))
+            .run(
+                &[Instruction::new(Action::Forward, 10)],
+                &mut DirectMovement,
+            )
+            .unwrap();
+        assert_eq!(diagonal.position(), Vector::new(7, 7));
+
+        assert_eq!(
+            plan_direct(
+                &Ship::default(),
+                Vector::new(isize::MIN, 0),
+                Heading::new(0)
+            ),
+            Err(NavigationError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_plan_waypoint() {
+        let ship = Ship::default();
+        assert_eq!(plan_waypoint(&ship, Vector::new(0, 0)).unwrap(), vec![]);
+        assert_eq!(
+            plan_waypoint(&ship, Vector::new(20, -2)).unwrap(),
+            vec![Instruction::new(Action::Forward, 2)]
+        );
+        assert_eq!(
+            plan_waypoint(&ship, Vector::new(3, 30)).unwrap(),
+            vec![
+                Instruction::new(Action::Right, 90),
+                Instruction::new(Action::Forward, 3)
+            ]
+        );
+        // Shifting the waypoint north twice as far lets us move 7 times
+        assert_eq!(
+            plan_waypoint(&ship, Vector::new(70, -21)).unwrap(),
+            vec![
+                Instruction::new(Action::North, 2),
+                Instruction::new(Action::Forward, 7)
+            ]
+        );
+        assert_eq!(plan_waypoint(&ship, Vector::new(0, 5)).unwrap().len(), 3);
+        assert_eq!(plan_waypoint(&ship, Vector::new(30, 0)).unwrap().len(), 2);
+        // 2^63 steps don't fit in an instruction, twice the waypoint does
+        let target = Vector::new(isize::MIN, 0);
+        let plan = plan_waypoint(&ship, target).unwrap();
+        assert_eq!(
+            plan,
+            vec![
+                Instruction::new(Action::West, 12),
+                Instruction::new(Action::South, 1),
+                Instruction::new(Action::Forward, 1 << 62)
+            ]
+        );
+        assert_eq!(
+            navigate(&plan, &mut WaypointMovement).unwrap().position(),
+            target
+        );
+        let ship = Ship::default().with_position(Vector::new(1, 0));
+        assert_eq!(plan_waypoint(&ship, target), Err(NavigationError::Overflow));
+        // Shifting the waypoint would take a 2^63 step instruction
+        let ship = Ship::default().with_waypoint(Vector::new(isize::MAX - 1, 0));
+        assert_eq!(plan_waypoint(&ship, target), Err(NavigationError::Overflow));
+    }
+
+    #[test]
+    fn test_plan_replay() {
+        let ship = Ship::default();
+        let target = Vector::new(214, 72);
+        let plan = plan_waypoint(&ship, target).unwrap();
+        let replayed = navigate(&plan, &mut WaypointMovement).unwrap();
+        assert_eq!(replayed.position(), target);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_plan_direct_replays(
+            (x, y, heading) in (-1000isize..1000, -1000isize..1000, 0isize..4),
+            (tx, ty, target_heading) in (-1000isize..1000, -1000isize..1000, 0isize..4),
+        ) {
+            let ship = start(x, y, heading * 90, 10, -1);
+            let target = Vector::new(tx, ty);
+            let plan = plan_direct(&ship, target, Heading::new(target_heading * 90)).unwrap();
+            prop_assert!(plan.len() <= 3);
+            let replayed = start(x, y, heading * 90, 10, -1)
+                .run(&plan, &mut DirectMovement)
+                .unwrap();
+            prop_assert_eq!(replayed.position(), target);
+            prop_assert_eq!(replayed.heading(), Heading::new(target_heading * 90));
+        }
+
+        #[test]
+        fn prop_plan_waypoint_replays(
+            (x, y, wx, wy) in (-1000isize..1000, -1000isize..1000, -50isize..50, -50isize..50),
+            (tx, ty) in (-1000isize..1000, -1000isize..1000),
+        ) {
+            let ship = start(x, y, 0, wx, wy);
+            let target = Vector::new(tx, ty);
+            let plan = plan_waypoint(&ship, target).unwrap();
+            prop_assert!(plan.len() <= 3);
+            let replayed = start(x, y, 0, wx, wy)
+                .run(&plan, &mut WaypointMovement)
+                .unwrap();
+            prop_assert_eq!(replayed.position(), target);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! 2D vectors and headings for navigating the ship.
+//!
+//! Coordinates follow the puzzle's grid: x grows to the east and y grows to
+//! the south. Positive angles rotate clockwise.
+
+use super::Action;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NavigationError {
+    /// Grid vectors can only be rotated in multiples of 90 degrees
+    NotQuarterTurn(isize),
+    /// A coordinate or instruction value doesn't fit in an `isize`
+    Overflow,
+}
+
+impl fmt::Display for NavigationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotQuarterTurn(degrees) => {
+                write!(f, "{} degrees is not a multiple of 90", degrees)
+            }
+            Self::Overflow => write!(f, "coordinate overflow"),
+        }
+    }
+}
+
+/// How to measure the distance between two positions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    /// Sum of the absolute differences (taxicab)
+    Manhattan,
+    /// Largest absolute difference
+    Chebyshev,
+    /// Straight line distance
+    Euclidean,
+}
+
+impl Metric {
+    pub fn distance(self, from: Vector<isize>, to: Vector<isize>) -> Result<f64, NavigationError> {
+        let delta = to.checked_sub(from)?;
+        Ok(match self {
+            Self::Manhattan => delta.manhattan()? as f64,
+            Self::Chebyshev => delta.chebyshev() as f64,
+            Self::Euclidean => delta.to_f64().length(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vector<T> {
+    pub x: T,
+    pub y: T,
+}
+
+impl<T> Vector<T> {
+    pub fn new(x: T, y: T) -> Self {
+        Vector { x, y }
+    }
+}
+
+impl<T: Add<Output = T>> Add for Vector<T> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Vector::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl<T: Sub<Output = T>> Sub for Vector<T> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Vector::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl<T: Neg<Output = T>> Neg for Vector<T> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Vector::new(-self.x, -self.y)
+    }
+}
+
+impl<T: Mul<Output = T> + Copy> Mul<T> for Vector<T> {
+    type Output = Self;
+
+    fn mul(self, scalar: T) -> Self {
+        Vector::new(self.x * scalar, self.y * scalar)
+    }
+}
+
+impl Vector<isize> {
+    pub fn checked_add(self, other: Self) -> Result<Self, NavigationError> {
+        Ok(Vector::new(
+            self.x
+                .checked_add(other.x)
+                .ok_or(NavigationError::Overflow)?,
+            self.y
+                .checked_add(other.y)
+                .ok_or(NavigationError::Overflow)?,
+        ))
+    }
+
+    pub fn checked_sub(self, other: Self) -> Result<Self, NavigationError> {
+        Ok(Vector::new(
+            self.x
+                .checked_sub(other.x)
+                .ok_or(NavigationError::Overflow)?,
+            self.y
+                .checked_sub(other.y)
+                .ok_or(NavigationError::Overflow)?,
+        ))
+    }
+
+    pub fn checked_mul(self, scalar: isize) -> Result<Self, NavigationError> {
+        Ok(Vector::new(
+            self.x
+                .checked_mul(scalar)
+                .ok_or(NavigationError::Overflow)?,
+            self.y
+                .checked_mul(scalar)
+                .ok_or(NavigationError::Overflow)?,
+        ))
+    }
+
+    /// Rotates the vector exactly. Only multiples of 90 degrees keep the
+    /// vector on the grid, anything else is an error.
+    pub fn rotate(self, degrees: isize) -> Result<Self, NavigationError> {
+        if degrees % 90 != 0 {
+            return Err(NavigationError::NotQuarterTurn(degrees));
+        }
+        let neg = |v: isize| v.checked_neg().ok_or(NavigationError::Overflow);
+        Ok(match degrees.rem_euclid(360) / 90 {
+            0 => self,
+            1 => Vector::new(neg(self.y)?, self.x),
+            2 => Vector::new(neg(self.x)?, neg(self.y)?),
+            _ => Vector::new(self.y, neg(self.x)?),
+        })
+    }
+
+    /// Rotates by any angle, rounding the result to the closest grid point
+    pub fn rotate_rounded(self, degrees: isize) -> Result<Self, NavigationError> {
+        self.to_f64().rotate(degrees).round()
+    }
+
+    /// Manhattan distance from the origin
+    pub fn manhattan(self) -> Result<usize, NavigationError> {
+        self.x
+            .unsigned_abs()
+            .checked_add(self.y.unsigned_abs())
+            .ok_or(NavigationError::Overflow)
+    }
+
+    /// Chebyshev distance from the origin
+    pub fn chebyshev(self) -> usize {
+        self.x.unsigned_abs().max(self.y.unsigned_abs())
+    }
+
+    pub fn to_f64(self) -> Vector<f64> {
+        Vector::new(self.x as f64, self.y as f64)
+    }
+}
+
+impl Vector<f64> {
+    pub fn rotate(self, degrees: isize) -> Self {
+        // Quarter turns are done exactly to avoid drifting off the grid
+        if degrees % 90 == 0 {
+            return match degrees.rem_euclid(360) / 90 {
+                0 => self,
+                1 => Vector::new(-self.y, self.x),
+                2 => Vector::new(-self.x, -self.y),
+                _ => Vector::new(self.y, -self.x),
+            };
+        }
+        let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+        Vector::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
+
+    /// Rounds to the closest grid point
+    pub fn round(self) -> Result<Vector<isize>, NavigationError> {
+        let round = |v: f64| {
+            let v = v.round();
+            // isize::MAX as f64 rounds up to 2^63, which is out of range
+            if v >= isize::MIN as f64 && v < isize::MAX as f64 {
+                Ok(v as isize)
+            } else {
+                Err(NavigationError::Overflow)
+            }
+        };
+        Ok(Vector::new(round(self.x)?, round(self.y)?))
+    }
+
+    pub fn length(self) -> f64 {
+        self.x.hypot(self.y)
+    }
+}
+
+/// Direction in degrees, clockwise from east and normalised to `0..360`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Heading(isize);
+
+impl fmt::Display for Heading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Heading {
+    pub fn new(degrees: isize) -> Self {
+        Heading(degrees.rem_euclid(360))
+    }
+
+    /// The heading of a cardinal direction, `None` for the other actions
+    pub fn from_action(action: Action) -> Option<Self> {
+        match action {
+            Action::East => Some(Heading(0)),
+            Action::South => Some(Heading(90)),
+            Action::West => Some(Heading(180)),
+            Action::North => Some(Heading(270)),
+            _ => None,
+        }
+    }
+
+    /// The cardinal direction of the heading, if it is one
+    pub fn to_action(self) -> Option<Action> {
+        match self.0 {
+            0 => Some(Action::East),
+            90 => Some(Action::South),
+            180 => Some(Action::West),
+            270 => Some(Action::North),
+            _ => None,
+        }
+    }
+
+    pub fn degrees(self) -> isize {
+        self.0
+    }
+
+    pub fn rotate(self, degrees: isize) -> Self {
+        Heading::new(self.0 + degrees.rem_euclid(360))
+    }
+
+    pub fn unit(self) -> Vector<f64> {
+        Vector::new(1.0, 0.0).rotate(self.0)
+    }
+
+    /// Unit vector on the grid, only available for cardinal headings
+    pub fn grid_unit(self) -> Result<Vector<isize>, NavigationError> {
+        Vector::new(1, 0).rotate(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_rotate() {
+        let v = Vector::new(10, -1);
+        assert_eq!(v.rotate(90), Ok(Vector::new(1, 10)));
+        assert_eq!(v.rotate(-90), Ok(Vector::new(-1, -10)));
+        assert_eq!(v.rotate(180), Ok(Vector::new(-10, 1)));
+        assert_eq!(v.rotate(-270), Ok(Vector::new(1, 10)));
+        assert_eq!(v.rotate(720), Ok(v));
+        assert_eq!(v.rotate(45), Err(NavigationError::NotQuarterTurn(45)));
+    }
+
+    #[test]
+    fn test_float_rotate() {
+        let v = Vector::new(1.0, 0.0).rotate(45);
+        assert!((v.x - 0.5f64.sqrt()).abs() < 1e-12);
+        assert!((v.y - 0.5f64.sqrt()).abs() < 1e-12);
+        assert_eq!(Vector::new(2.5, -1.0).rotate(270), Vector::new(-1.0, -2.5));
+        assert_eq!(Vector::new(10, 0).rotate_rounded(30), Ok(Vector::new(9, 5)));
+        assert_eq!(
+            Vector::new(10, 0).rotate_rounded(-60),
+            Ok(Vector::new(5, -9))
+        );
+        assert_eq!(
+            Vector::new(isize::MAX, isize::MAX).rotate_rounded(45),
+            Err(NavigationError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_checked() {
+        let v = Vector::new(isize::MAX, isize::MIN);
+        assert_eq!(v.rotate(90), Err(NavigationError::Overflow));
+        assert_eq!(v.rotate(270), Ok(Vector::new(isize::MIN, -isize::MAX)));
+        assert_eq!(
+            v.checked_add(Vector::new(1, 0)),
+            Err(NavigationError::Overflow)
+        );
+        assert_eq!(
+            v.checked_sub(Vector::new(0, 1)),
+            Err(NavigationError::Overflow)
+        );
+        assert_eq!(
+            Vector::new(2, 3).checked_mul(isize::MAX),
+            Err(NavigationError::Overflow)
+        );
+        assert_eq!(v.manhattan(), Ok(usize::MAX));
+        assert_eq!(
+            Vector::new(isize::MIN, isize::MIN).manhattan(),
+            Err(NavigationError::Overflow)
+        );
+        assert_eq!(v.chebyshev(), isize::MIN.unsigned_abs());
+    }
+
+    #[test]
+    fn test_metrics() {
+        let (from, to) = (Vector::new(-1, 2), Vector::new(2, -2));
+        assert_eq!(Metric::Manhattan.distance(from, to), Ok(7.0));
+        assert_eq!(Metric::Chebyshev.distance(from, to), Ok(4.0));
+        assert_eq!(Metric::Euclidean.distance(from, to), Ok(5.0));
+        assert_eq!(
+            Metric::Euclidean.distance(Vector::new(isize::MIN, 0), to),
+            Err(NavigationError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_heading() {
+        let heading = Heading::from_action(Action::East).unwrap();
+        assert_eq!(heading.rotate(90).to_action(), Some(Action::South));
+        assert_eq!(heading.rotate(-90).to_action(), Some(Action::North));
+        assert_eq!(heading.rotate(-405), Heading::new(315));
+        assert_eq!(Heading::new(90).rotate(isize::MAX), Heading::new(97));
+        assert_eq!(heading.rotate(45).to_action(), None);
+        assert_eq!(Heading::new(180).grid_unit(), Ok(Vector::new(-1, 0)));
+        assert_eq!(
+            Heading::new(135).grid_unit(),
+            Err(NavigationError::NotQuarterTurn(135))
+        );
+        assert_eq!(Heading::from_action(Action::Forward), None);
+    }
+}
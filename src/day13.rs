@@ -1,49 +1,196 @@
+use num::{BigInt, ToPrimitive};
+use std::fmt;
+
+pub mod crt;
+
 pub struct Timetable {
     time: usize,
     busses: Vec<Option<usize>>,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    MissingTime,
+    InvalidTime(String),
+    MissingBusses,
+    /// `position` is the token's index in the bus list, i.e. its offset
+    InvalidBus {
+        position: usize,
+        token: String,
+    },
+    ZeroBus {
+        position: usize,
+    },
+    /// Every slot is `x`
+    NoBusInService,
+    UnexpectedLine(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingTime => write!(f, "missing timestamp"),
+            Self::InvalidTime(s) => write!(f, "invalid timestamp {:?}", s),
+            Self::MissingBusses => write!(f, "missing bus list"),
+            Self::InvalidBus { position, token } => {
+                write!(f, "invalid bus ID {:?} at position {}", token, position)
+            }
+            Self::ZeroBus { position } => write!(f, "bus ID 0 at position {}", position),
+            Self::NoBusInService => write!(f, "no bus in service"),
+            Self::UnexpectedLine(s) => write!(f, "unexpected line {:?}", s),
+        }
+    }
+}
+
 impl std::str::FromStr for Timetable {
-    type Err = ();
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut lines = s.lines();
-
-        Ok(Timetable {
-            time: lines.next().map(|x| x.parse().unwrap()).unwrap(),
-            busses: lines
-                .next()
-                .map(|line| {
-                    line.split(',')
-                        .map(|x| x.parse().ok())
-                        .collect::<Vec<Option<usize>>>()
-                })
-                .unwrap(),
-        })
+        let mut lines = s.lines().map(str::trim).filter(|line| !line.is_empty());
+
+        let time = lines.next().ok_or(ParseError::MissingTime)?;
+        let time = time
+            .parse()
+            .map_err(|_| ParseError::InvalidTime(time.to_string()))?;
+        let busses = lines
+            .next()
+            .ok_or(ParseError::MissingBusses)?
+            .split(',')
+            .map(str::trim)
+            .enumerate()
+            .map(|(position, token)| match token {
+                "x" => Ok(None),
+                _ => match token.parse() {
+                    Ok(0) => Err(ParseError::ZeroBus { position }),
+                    Ok(bus) => Ok(Some(bus)),
+                    Err(_) => Err(ParseError::InvalidBus {
+                        position,
+                        token: token.to_string(),
+                    }),
+                },
+            })
+            .collect::<Result<Vec<Option<usize>>, ParseError>>()?;
+        if let Some(line) = lines.next() {
+            return Err(ParseError::UnexpectedLine(line.to_string()));
+        }
+        if busses.iter().all(Option::is_none) {
+            return Err(ParseError::NoBusInService);
+        }
+
+        Ok(Timetable { time, busses })
     }
 }
 
+/// A bus leaving the station. Ordered by time, then by bus ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Departure {
+    pub time: usize,
+    pub bus: usize,
+}
+
 impl Timetable {
-    fn next_bus(&self) -> (usize, usize) {
-        let busses = self.busses.iter();
-
-        busses
-            .scan((0, usize::MAX), |state, bus| {
-                match bus {
-                    Some(b) => {
-                        let delta = b - (self.time % b);
-                        if delta < state.1 {
-                            *state = (*b, delta)
-                        }
-                    }
-                    None => (),
-                }
-                Some(*state)
+    pub fn time(&self) -> usize {
+        self.time
+    }
+
+    /// Bus IDs in service, in timetable order
+    pub fn bus_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.busses.iter().filter_map(|bus| *bus)
+    }
+
+    /// The earliest bus departing at or after the timetable's time as
+    /// `(bus, wait)`. Ties go to the lowest bus ID.
+    pub fn next_bus(&self) -> (usize, usize) {
+        let departure = self
+            .bus_ids()
+            .map(|bus| Departure {
+                time: next_departure(bus, self.time),
+                bus,
+            })
+            .min()
+            .unwrap();
+        (departure.bus, departure.time - self.time)
+    }
+
+    /// Every departure within `start..=end`, in departure order
+    pub fn departures_between(&self, start: usize, end: usize) -> Vec<Departure> {
+        let mut departures: Vec<Departure> = self
+            .bus_ids()
+            .flat_map(|bus| {
+                (next_departure(bus, start)..=end)
+                    .step_by(bus)
+                    .map(move |time| Departure { time, bus })
+            })
+            .collect();
+        departures.sort_unstable();
+        departures
+    }
+
+    /// The next `k` departure times of each bus at or after the timetable's
+    /// time, in timetable order
+    pub fn next_departures(&self, k: usize) -> Vec<(usize, Vec<usize>)> {
+        self.bus_ids()
+            .map(|bus| {
+                let first = next_departure(bus, self.time);
+                (bus, (0..k).map(|i| first + i * bus).collect())
             })
-            .last()
-            .unwrap()
-            .to_owned()
+            .collect()
     }
+
+    /// The first time `t` where every `(bus, offset)` departs at `t + offset`
+    pub fn first_alignment(constraints: &[(usize, usize)]) -> Result<BigInt, crt::CrtError> {
+        let congruences: Vec<(BigInt, BigInt)> = constraints
+            .iter()
+            .map(|&(bus, offset)| (-BigInt::from(offset), BigInt::from(bus)))
+            .collect();
+        crt::solve(&congruences).map(|(time, _)| time)
+    }
+
+    /// Renders the departures within `start..=end` in the same layout as the
+    /// puzzle, with a `D` for every bus that departs
+    pub fn grid(&self, start: usize, end: usize) -> String {
+        let labels: Vec<(usize, String)> = self
+            .bus_ids()
+            .map(|bus| (bus, format!("bus {}", bus)))
+            .collect();
+        let time_width = end.to_string().len().max("time".len()) + 3;
+
+        let mut grid = format!("{:<width$}", "time", width = time_width);
+        for (_, label) in &labels {
+            grid.push_str(&format!("{:<width$}", label, width = column_width(label)));
+        }
+        grid.truncate(grid.trim_end().len());
+        grid.push('\n');
+
+        for time in start..=end {
+            let mut line = format!("{:<width$}", time, width = time_width);
+            for (bus, label) in &labels {
+                let mark = if time % bus == 0 { "D" } else { "." };
+                // Centered below the label
+                let pad = (label.len() - 1) / 2;
+                line.push_str(&format!(
+                    "{:pad$}{:<width$}",
+                    "",
+                    mark,
+                    pad = pad,
+                    width = column_width(label) - pad
+                ));
+            }
+            grid.push_str(line.trim_end());
+            grid.push('\n');
+        }
+        grid
+    }
+}
+
+// Columns are 8 wide like in the puzzle, unless the label needs more
+fn column_width(label: &str) -> usize {
+    (label.len() + 2).max(8)
+}
+
+// First departure of `bus` at or after `time`
+fn next_departure(bus: usize, time: usize) -> usize {
+    time.div_ceil(bus) * bus
 }
 
 #[aoc_generator(day13)]
@@ -59,41 +206,16 @@ pub fn part1(timetable: &Timetable) -> usize {
 
 #[aoc(day13, part2)]
 pub fn part2(timetable: &Timetable) -> usize {
-    use num::Integer;
-
-    let b: Vec<(usize, usize)> = timetable
+    let constraints: Vec<(usize, usize)> = timetable
         .busses
-        .clone()
         .iter()
         .enumerate()
-        .filter(|(_, x)| x.is_some())
-        .map(|(i, x)| (i, x.unwrap()))
+        .filter_map(|(offset, bus)| bus.map(|b| (b, offset)))
         .collect();
-    let mut increment = b[0].1;
-    let mut time = 0usize;
-    let mut index_found: usize = 0;
-    loop {
-        time += increment;
-        let mut all = true;
-        for (offset, value) in &b {
-            let num = time + offset;
-            if num % value == 0 {
-                if *offset > index_found {
-                    increment = increment.lcm(value);
-                    index_found = *offset;
-                }
-                continue;
-            }
-            all &= false;
-            break;
-        }
-
-        // All matches, we're done
-        if all {
-            break;
-        }
-    }
-    time
+    Timetable::first_alignment(&constraints)
+        .unwrap()
+        .to_usize()
+        .unwrap()
 }
 
 #[cfg(test)]
@@ -149,4 +271,125 @@ mod tests {
         let timetable: Timetable = input_generator("0\n1789,37,47,1889");
         assert_eq!(part2(&timetable), 1202161486);
     }
+
+    #[test]
+    fn test_day13_next_bus_ties() {
+        let timetable: Timetable = input_generator("10\n7,5,3,x,2");
+        assert_eq!(timetable.next_bus(), (2, 0));
+        // Both 3 and 2 leave at 12
+        let timetable: Timetable = input_generator("11\n7,5,3,x,2");
+        assert_eq!(timetable.next_bus(), (2, 1));
+        let timetable: Timetable = input_generator("13\n7,5,x,3");
+        assert_eq!(timetable.next_bus(), (7, 1));
+    }
+
+    #[test]
+    fn test_day13_departures() {
+        let timetable: Timetable = input_generator(SAMPLE_INPUT);
+        assert_eq!(
+            timetable.departures_between(936, 945),
+            vec![
+                Departure { time: 936, bus: 13 },
+                Departure { time: 938, bus: 7 },
+                Departure { time: 944, bus: 59 },
+                Departure { time: 945, bus: 7 },
+            ]
+        );
+        assert_eq!(
+            timetable.next_departures(2),
+            vec![
+                (7, vec![945, 952]),
+                (13, vec![949, 962]),
+                (59, vec![944, 1003]),
+                (31, vec![961, 992]),
+                (19, vec![950, 969]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_day13_first_alignment() {
+        assert_eq!(
+            Timetable::first_alignment(&[(17, 0), (13, 2), (19, 3)]),
+            Ok(BigInt::from(3417))
+        );
+        // Subset of the sample, bus 59 four minutes after bus 7
+        assert_eq!(
+            Timetable::first_alignment(&[(7, 0), (59, 4)]),
+            Ok(BigInt::from(350))
+        );
+        assert_eq!(
+            Timetable::first_alignment(&[(4, 0), (6, 1)]),
+            Err(crt::CrtError::NoSolution)
+        );
+    }
+
+    #[test]
+    fn test_day13_grid() {
+        let timetable: Timetable = input_generator(SAMPLE_INPUT);
+        assert_eq!(
+            timetable.grid(929, 931),
+            "time   bus 7   bus 13  bus 59  bus 31  bus 19
+929      .       .       .       .       .
+930      .       .       .       D       .
+931      D       .       .       .       D
+"
+        );
+    }
+
+    #[test]
+    fn test_day13_parser_whitespace() {
+        let timetable: Timetable = "\n  939 \r\n 7, 13,x ,x,59\n\n\n".parse().unwrap();
+        assert_eq!(timetable.time, 939);
+        assert_eq!(
+            timetable.busses,
+            vec![Some(7), Some(13), None, None, Some(59)]
+        );
+    }
+
+    #[test]
+    fn test_day13_parser_errors() {
+        assert_eq!("".parse::<Timetable>().err(), Some(ParseError::MissingTime));
+        assert_eq!(
+            "93x\n7".parse::<Timetable>().err(),
+            Some(ParseError::InvalidTime("93x".to_string()))
+        );
+        assert_eq!(
+            "939\n".parse::<Timetable>().err(),
+            Some(ParseError::MissingBusses)
+        );
+        assert_eq!(
+            "939\n7,13,1x,59".parse::<Timetable>().err(),
+            Some(ParseError::InvalidBus {
+                position: 2,
+                token: "1x".to_string()
+            })
+        );
+        assert_eq!(
+            "939\n7,,59".parse::<Timetable>().err(),
+            Some(ParseError::InvalidBus {
+                position: 1,
+                token: "".to_string()
+            })
+        );
+        assert_eq!(
+            "939\nx,0".parse::<Timetable>().err(),
+            Some(ParseError::ZeroBus { position: 1 })
+        );
+        assert_eq!(
+            "939\nx,X".parse::<Timetable>().err(),
+            Some(ParseError::InvalidBus {
+                position: 1,
+                token: "X".to_string()
+            })
+        );
+        assert_eq!(
+            "939\nx,x".parse::<Timetable>().err(),
+            Some(ParseError::NoBusInService)
+        );
+        assert_eq!(
+            "939\n7\n13".parse::<Timetable>().err(),
+            Some(ParseError::UnexpectedLine("13".to_string()))
+        );
+    }
 }
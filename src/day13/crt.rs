@@ -0,0 +1,109 @@
+//! Chinese Remainder Theorem solver for systems of congruences
+//! `x ≡ remainder (mod modulus)`.
+//!
+//! The moduli don't have to be coprime. The solver is generic over signed
+//! integers; intermediate products can grow to the square of the combined
+//! modulus, so use `BigInt` when that doesn't fit in a fixed-size integer.
+
+use num::{Integer, Signed};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CrtError {
+    /// Two congruences contradict each other
+    NoSolution,
+    /// Moduli must be positive
+    InvalidModulus,
+}
+
+impl fmt::Display for CrtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoSolution => write!(f, "congruences have no common solution"),
+            Self::InvalidModulus => write!(f, "modulus must be positive"),
+        }
+    }
+}
+
+/// Solves the system of `(remainder, modulus)` congruences.
+///
+/// Returns the smallest non-negative solution together with the combined
+/// modulus (the LCM of all moduli), every solution is congruent to it. An
+/// empty system is solved by everything, i.e. `(0, 1)`.
+pub fn solve<T>(congruences: &[(T, T)]) -> Result<(T, T), CrtError>
+where
+    T: Integer + Signed + Clone,
+{
+    congruences
+        .iter()
+        .try_fold((T::zero(), T::one()), |acc, congruence| {
+            combine(acc, congruence.clone())
+        })
+}
+
+/// Merges two congruences into one, using the extended Euclidean algorithm
+pub fn combine<T>((r1, m1): (T, T), (r2, m2): (T, T)) -> Result<(T, T), CrtError>
+where
+    T: Integer + Signed + Clone,
+{
+    if !m1.is_positive() || !m2.is_positive() {
+        return Err(CrtError::InvalidModulus);
+    }
+    // p * m1 + q * m2 = g
+    let egcd = m1.extended_gcd(&m2);
+    let g = egcd.gcd;
+    let (k, rem) = (r2 - r1.clone()).div_rem(&g);
+    if !rem.is_zero() {
+        return Err(CrtError::NoSolution);
+    }
+    let lcm = m1.clone() / g * m2;
+    // x = r1 + m1 * p * (r2 - r1) / g satisfies both congruences
+    let step = (egcd.x * k).mod_floor(&lcm);
+    let x = (r1 + m1 * step).mod_floor(&lcm);
+    Ok((x, lcm))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigInt;
+
+    #[test]
+    fn test_solve_coprime() {
+        assert_eq!(solve(&[(2i64, 3), (3, 5), (2, 7)]), Ok((23, 105)));
+        assert_eq!(solve(&[(0i64, 17), (-2, 13), (-3, 19)]), Ok((3417, 4199)));
+        assert_eq!(solve::<i64>(&[]), Ok((0, 1)));
+    }
+
+    #[test]
+    fn test_solve_non_coprime() {
+        assert_eq!(solve(&[(2i64, 6), (8, 10)]), Ok((8, 30)));
+        assert_eq!(solve(&[(3i64, 4), (3, 4)]), Ok((3, 4)));
+        assert_eq!(solve(&[(1i64, 4), (2, 6)]), Err(CrtError::NoSolution));
+        assert_eq!(solve(&[(1i64, 0)]), Err(CrtError::InvalidModulus));
+        assert_eq!(solve(&[(1i64, -3)]), Err(CrtError::InvalidModulus));
+    }
+
+    #[test]
+    fn test_solve_big() {
+        // Product of the moduli is far larger than u128::MAX
+        let primes: [u64; 6] = [
+            18446744073709551557,
+            18446744073709551533,
+            18446744073709551521,
+            18446744073709551437,
+            18446744073709551427,
+            18446744073709551359,
+        ];
+        let congruences: Vec<(BigInt, BigInt)> = primes
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| (BigInt::from(i), BigInt::from(p)))
+            .collect();
+        let (x, lcm) = solve(&congruences).unwrap();
+        assert!(lcm > BigInt::from(u128::MAX));
+        for (r, m) in &congruences {
+            assert_eq!(&x % m, *r);
+        }
+    }
+}
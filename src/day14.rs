@@ -1,125 +1,477 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
 
-#[derive(Clone)]
-pub struct Instruction {
-    mask: String,
-    mem: Vec<(usize, u64)>,
+use nom::{
+    bytes::complete::tag,
+    character::complete::{alphanumeric1, char, digit1, space0},
+    combinator::all_consuming,
+    sequence::tuple,
+    IResult,
+};
+
+pub mod floating;
+
+use floating::{AddressPattern, FloatingMemory};
+
+/// Masks, addresses and values are all 36 bits wide
+pub const WORD_BITS: usize = 36;
+const WORD_MAX: u64 = (1 << WORD_BITS) - 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    InvalidMaskLength(usize),
+    /// `position` counts from the most significant bit
+    InvalidMaskCharacter {
+        position: usize,
+        found: char,
+    },
+    AddressOutOfRange(String),
+    ValueOutOfRange(String),
+    /// A write before the first mask
+    MissingMask,
+    InvalidSyntax(String),
+}
+
+/// A parse error with its 1-based line number
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: ParseErrorKind,
 }
 
-impl Default for Instruction {
-    fn default() -> Self {
-        Self {
-            mask: String::default(),
-            mem: Vec::default(),
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidMaskLength(n) => {
+                write!(f, "mask is {} bits, expected {}", n, WORD_BITS)
+            }
+            Self::InvalidMaskCharacter { position, found } => {
+                write!(f, "invalid mask bit {:?} at position {}", found, position)
+            }
+            Self::AddressOutOfRange(s) => write!(f, "address {} is wider than 36 bits", s),
+            Self::ValueOutOfRange(s) => write!(f, "value {} is wider than 36 bits", s),
+            Self::MissingMask => write!(f, "memory write before the first mask"),
+            Self::InvalidSyntax(s) => write!(f, "invalid instruction {:?}", s),
         }
     }
 }
 
-#[aoc_generator(day14)]
-pub fn input_generator(input: &str) -> Vec<Instruction> {
-    let mut instructions: Vec<Instruction> = Vec::default();
-    let mut ins: Instruction = Instruction::default();
-    for line in input.lines() {
-        let mut parts = line.split(" = ");
-        let op = parts.next().unwrap();
-        let value = parts.next().unwrap();
-        if op == "mask" {
-            if !ins.mask.is_empty() {
-                instructions.push(ins);
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+/// A parsed bitmask, each bit position is set in exactly one of the fields
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Mask {
+    /// Bits overwritten with 1
+    pub ones: u64,
+    /// Bits overwritten with 0 (only in version 1)
+    pub zeros: u64,
+    /// `X` bits
+    pub floating: u64,
+}
+
+impl std::str::FromStr for Mask {
+    type Err = ParseErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let length = s.chars().count();
+        if length != WORD_BITS {
+            return Err(ParseErrorKind::InvalidMaskLength(length));
+        }
+        let mut mask = Mask::default();
+        for (position, c) in s.chars().enumerate() {
+            let bit = 1 << (WORD_BITS - 1 - position);
+            match c {
+                '1' => mask.ones |= bit,
+                '0' => mask.zeros |= bit,
+                'X' => mask.floating |= bit,
+                found => return Err(ParseErrorKind::InvalidMaskCharacter { position, found }),
             }
-            ins = Instruction {
-                mask: value.to_string(),
-                ..Instruction::default()
-            };
-        } else {
-            let registry: usize = op
-                .chars()
-                .filter(|&c| c.is_numeric())
-                .collect::<String>()
-                .parse()
-                .unwrap();
-            ins.mem.push((registry, value.parse().unwrap()));
         }
+        Ok(mask)
     }
-    instructions.push(ins);
-    instructions
 }
 
-#[aoc(day14, part1)]
-pub fn part1(instructions: &Vec<Instruction>) -> u64 {
-    let mut memory = HashMap::default();
-    for ins in instructions {
-        run_instruction(&mut memory, &ins);
+impl Mask {
+    /// Version 1: overwrite the value's bits where the mask is 0 or 1
+    pub fn apply_value(&self, value: u64) -> u64 {
+        value & !self.zeros | self.ones
+    }
+
+    /// Version 2: the addresses the mask decodes `address` into, as a pattern
+    pub fn pattern(&self, address: u64) -> AddressPattern {
+        AddressPattern::new(address | self.ones, self.floating)
+    }
+
+    /// Version 2: every address the mask decodes `address` into
+    pub fn addresses(&self, address: u64) -> impl Iterator<Item = u64> {
+        let base = (address | self.ones) & !self.floating;
+        let floating = self.floating;
+        // Walk through every subset of the floating bits, ending with the
+        // empty set
+        let mut subset = Some(floating);
+        std::iter::from_fn(move || {
+            let current = subset?;
+            subset = if current == 0 {
+                None
+            } else {
+                Some((current - 1) & floating)
+            };
+            Some(base | current)
+        })
     }
-    memory.values().sum::<u64>()
 }
 
-#[aoc(day14, part2)]
-pub fn part2(instructions: &Vec<Instruction>) -> u64 {
-    let mut memory: HashMap<u64, u64> = HashMap::default();
-    for ins in instructions {
-        let (set_bits, _) = get_masks(&ins);
-        // Get the position of all the X's
-        let ones: Vec<usize> = ins
-            .mask
-            .chars()
-            .rev()
-            .enumerate()
-            .filter_map(|(i, c)| match c {
-                'X' => Some(i),
-                _ => None,
-            })
-            .collect();
-        for (addr, value) in &ins.mem {
-            // Start with all the bits set
-            let mut addresses = vec![*addr as u64 | set_bits];
-            for i in &ones {
-                // For each set bit in &ones, create a variant with it set and
-                // one with it off for each item in addresses
-                addresses = addresses
-                    .iter()
-                    .flat_map(|a| vec![a & !(1 << i), a | (1 << i)])
-                    .collect();
+#[derive(Clone, Debug, Default)]
+pub struct Instruction {
+    /// Line of the mask in the program
+    line: usize,
+    mask: Mask,
+    mem: Vec<(u64, u64)>,
+}
+
+impl Instruction {
+    /// 1-based line of the mask in the program
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecoderVersion {
+    /// The mask modifies the written values
+    V1,
+    /// The mask modifies the addresses written to
+    V2,
+}
+
+/// Version 2 writes through a mask with more `X` bits are refused, as each
+/// one fills 2^n memory cells. `FloatingMemory` has no such limit.
+pub const MAX_FLOATING_BITS: u32 = 16;
+
+/// A write the computer refused
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WriteError {
+    /// The mask decodes `address` into too many addresses
+    TooManyFloatingBits { address: u64, floating: u32 },
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooManyFloatingBits { address, floating } => write!(
+                f,
+                "mask has {} floating bits for address {}, at most {} can be expanded",
+                floating, address, MAX_FLOATING_BITS
+            ),
+        }
+    }
+}
+
+pub struct DockingComputer {
+    version: DecoderVersion,
+    mask: Mask,
+    memory: HashMap<u64, u64>,
+}
+
+impl DockingComputer {
+    pub fn new(version: DecoderVersion) -> Self {
+        DockingComputer {
+            version,
+            mask: Mask::default(),
+            memory: HashMap::default(),
+        }
+    }
+
+    pub fn set_mask(&mut self, mask: Mask) {
+        self.mask = mask;
+    }
+
+    /// Writes `value` to `address` through the current mask. Version 2
+    /// refuses masks with more than `MAX_FLOATING_BITS` floating bits.
+    pub fn write(&mut self, address: u64, value: u64) -> Result<(), WriteError> {
+        match self.version {
+            DecoderVersion::V1 => {
+                self.memory.insert(address, self.mask.apply_value(value));
+            }
+            DecoderVersion::V2 => {
+                let floating = self.mask.floating.count_ones();
+                if floating > MAX_FLOATING_BITS {
+                    return Err(WriteError::TooManyFloatingBits { address, floating });
+                }
+                for a in self.mask.addresses(address) {
+                    self.memory.insert(a, value);
+                }
             }
+        }
+        Ok(())
+    }
 
-            for a in &addresses {
-                memory.insert(*a, *value);
+    /// Runs every instruction of `program`, stopping at the first write that
+    /// is refused
+    pub fn run(&mut self, program: &[Instruction]) -> Result<(), WriteError> {
+        for ins in program {
+            self.set_mask(ins.mask);
+            match self.version {
+                DecoderVersion::V1 => run_instruction(&mut self.memory, ins),
+                DecoderVersion::V2 => {
+                    for &(address, value) in &ins.mem {
+                        self.write(address, value)?;
+                    }
+                }
             }
         }
+        Ok(())
+    }
+
+    /// Value at `address`, memory starts out as all zeros
+    pub fn read(&self, address: u64) -> u64 {
+        self.memory.get(&address).copied().unwrap_or(0)
+    }
+
+    /// Every address that has been written to
+    pub fn memory(&self) -> &HashMap<u64, u64> {
+        &self.memory
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.memory.values().sum()
+    }
+
+    /// Every written `(address, value)` sorted by address. See
+    /// `FloatingMemory::dump` for version 2 memory without expanding it.
+    pub fn dump(&self) -> Vec<(u64, u64)> {
+        let mut cells: Vec<(u64, u64)> = self.memory.iter().map(|(&a, &v)| (a, v)).collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    /// One line per address with the value in decimal and binary
+    pub fn dump_text(&self) -> String {
+        self.dump()
+            .iter()
+            .map(|&(address, value)| {
+                format!(
+                    "{:>11} {:>11} {:0width$b}\n",
+                    address,
+                    value,
+                    value,
+                    width = WORD_BITS
+                )
+            })
+            .collect()
+    }
+
+    pub fn dump_json(&self) -> String {
+        let cells: Vec<String> = self
+            .dump()
+            .iter()
+            .map(|&(address, value)| {
+                format!(
+                    "{{\"address\":{},\"value\":{},\"binary\":\"{:0width$b}\"}}",
+                    address,
+                    value,
+                    value,
+                    width = WORD_BITS
+                )
+            })
+            .collect();
+        format!("[{}]", cells.join(","))
+    }
+}
+
+/// Addresses where the two decoder versions leave different values
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryDiff {
+    pub addresses: AddressPattern,
+    /// `None` if the addresses were never written
+    pub v1: Option<u64>,
+    pub v2: Option<u64>,
+}
+
+impl fmt::Display for MemoryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |value: Option<u64>| value.map_or("-".to_string(), |v| v.to_string());
+        write!(
+            f,
+            "{} {:>11} {:>11}",
+            self.addresses,
+            show(self.v1),
+            show(self.v2)
+        )
+    }
+}
+
+/// Runs `program` with both decoder versions and returns where the final
+/// memory differs, sorted by address. Addresses only version 2 wrote to are
+/// kept as patterns, so floating bits aren't expanded.
+pub fn memory_diff(program: &[Instruction]) -> Vec<MemoryDiff> {
+    let mut v1 = HashMap::new();
+    for ins in program {
+        run_instruction(&mut v1, ins);
+    }
+    let v2 = floating_memory(program);
+
+    let mut diff: Vec<MemoryDiff> = v1
+        .iter()
+        .map(|(&address, &value)| MemoryDiff {
+            addresses: AddressPattern::new(address, 0),
+            v1: Some(value),
+            v2: v2.get(address),
+        })
+        .filter(|diff| diff.v1 != diff.v2)
+        .collect();
+    for (pattern, value) in v2.dump() {
+        let mut pieces = vec![pattern];
+        for &address in v1.keys() {
+            let written = AddressPattern::new(address, 0);
+            pieces = pieces.iter().flat_map(|p| p.subtract(&written)).collect();
+        }
+        diff.extend(pieces.into_iter().map(|addresses| MemoryDiff {
+            addresses,
+            v1: None,
+            v2: Some(value),
+        }));
+    }
+    // Patterns don't overlap, so neither do their lowest addresses
+    diff.sort_unstable_by_key(|d| d.addresses.address());
+    diff
+}
+
+/// Runs `program` with version 2 of the decoder without expanding the
+/// floating addresses
+pub fn floating_memory(program: &[Instruction]) -> FloatingMemory {
+    let mut memory = FloatingMemory::default();
+    for ins in program {
+        for &(address, value) in &ins.mem {
+            memory.write(ins.mask.pattern(address), value);
+        }
+    }
+    memory
+}
+
+enum Line {
+    Mask(Mask),
+    Write(u64, u64),
+}
+
+fn take_mask(i: &str) -> IResult<&str, &str> {
+    let (i, _) = tuple((tag("mask"), space0, char('='), space0))(i)?;
+    alphanumeric1(i)
+}
+
+fn take_write(i: &str) -> IResult<&str, (&str, &str)> {
+    let (i, (_, address, _, _, _, _, value)) = tuple((
+        tag("mem["),
+        digit1,
+        char(']'),
+        space0,
+        char('='),
+        space0,
+        digit1,
+    ))(i)?;
+    Ok((i, (address, value)))
+}
+
+// 36 bit unsigned number, `digits` is known to only contain digits
+fn word(digits: &str) -> Option<u64> {
+    digits.parse().ok().filter(|&n| n <= WORD_MAX)
+}
+
+// `None` for blank lines and comments, everything after a `#` is ignored
+fn parse_line(line: &str) -> Result<Option<Line>, ParseErrorKind> {
+    let line = line.split('#').next().unwrap().trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if let Ok((_, mask)) = all_consuming(take_mask)(line) {
+        return Ok(Some(Line::Mask(mask.parse()?)));
+    }
+    if let Ok((_, (address, value))) = all_consuming(take_write)(line) {
+        let address =
+            word(address).ok_or_else(|| ParseErrorKind::AddressOutOfRange(address.to_string()))?;
+        let value =
+            word(value).ok_or_else(|| ParseErrorKind::ValueOutOfRange(value.to_string()))?;
+        return Ok(Some(Line::Write(address, value)));
+    }
+    Err(ParseErrorKind::InvalidSyntax(line.to_string()))
+}
+
+/// Parses a program, grouping the writes under the mask preceding them
+pub fn parse_program(input: &str) -> Result<Vec<Instruction>, ParseError> {
+    let mut instructions: Vec<Instruction> = Vec::default();
+    for (i, line) in input.lines().enumerate() {
+        let error = |kind| ParseError { line: i + 1, kind };
+        match parse_line(line).map_err(error)? {
+            Some(Line::Mask(mask)) => instructions.push(Instruction {
+                line: i + 1,
+                mask,
+                mem: Vec::new(),
+            }),
+            Some(Line::Write(address, value)) => instructions
+                .last_mut()
+                .ok_or_else(|| error(ParseErrorKind::MissingMask))?
+                .mem
+                .push((address, value)),
+            None => {}
+        }
     }
-    memory.values().sum::<u64>()
+    Ok(instructions)
+}
+
+#[aoc_generator(day14)]
+pub fn input_generator(input: &str) -> Vec<Instruction> {
+    parse_program(input).unwrap()
 }
 
-fn get_masks(instruction: &Instruction) -> (u64, u64) {
-    (
-        u64::from_str_radix(&instruction.mask.replace("X", "1"), 2).unwrap(),
-        u64::from_str_radix(&instruction.mask.replace("X", "0"), 2).unwrap(),
-    )
+#[aoc(day14, part1)]
+pub fn part1(instructions: &Vec<Instruction>) -> u64 {
+    let mut computer = DockingComputer::new(DecoderVersion::V1);
+    computer.run(instructions).unwrap();
+    computer.sum()
+}
+
+#[aoc(day14, part2)]
+pub fn part2(instructions: &Vec<Instruction>) -> u64 {
+    u64::try_from(floating_memory(instructions).sum()).unwrap()
 }
 
-fn run_instruction(memory: &mut HashMap<usize, u64>, instruction: &Instruction) {
-    let (set_bits, clear_bits) = get_masks(&instruction);
+fn run_instruction(memory: &mut HashMap<u64, u64>, instruction: &Instruction) {
     for (key, value) in &instruction.mem {
-        memory.insert(*key, value & set_bits | clear_bits);
+        memory.insert(*key, instruction.mask.apply_value(*value));
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     static SAMPLE_INPUT: &'static str = "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X
 mem[8] = 11
 mem[7] = 101
 mem[8] = 0";
 
+    static SAMPLE_INPUT_2: &'static str = "mask = 000000000000000000000000000000X1001X
+mem[42] = 100
+mask = 00000000000000000000000000000000X0XX
+mem[26] = 1";
+
     #[test]
     fn test_input_generator() {
         let ins = input_generator(SAMPLE_INPUT);
         assert_eq!(ins.len(), 1);
         assert_eq!(
             ins[0].mask,
-            "XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X".to_string()
+            Mask {
+                ones: 0b1000000,
+                zeros: 0b10,
+                floating: 0xFFFFFFFFF & !0b1000010,
+            }
         );
         assert_eq!(ins[0].mem, vec![(8, 11), (7, 101), (8, 0)]);
     }
@@ -141,11 +493,231 @@ mem[8] = 0";
 
     #[test]
     fn test_part2() {
-        let input = "mask = 000000000000000000000000000000X1001X
-mem[42] = 100
-mask = 00000000000000000000000000000000X0XX
-mem[26] = 1";
-        let ins = &input_generator(input);
+        let ins = &input_generator(SAMPLE_INPUT_2);
         assert_eq!(part2(ins), 208);
     }
+
+    #[test]
+    fn test_mask_addresses() {
+        let mask: Mask = "000000000000000000000000000000X1001X".parse().unwrap();
+        let mut addresses: Vec<u64> = mask.addresses(42).collect();
+        addresses.sort_unstable();
+        assert_eq!(addresses, vec![26, 27, 58, 59]);
+
+        let mask: Mask = "000000000000000000000000000000000000".parse().unwrap();
+        assert_eq!(mask.addresses(42).collect::<Vec<u64>>(), vec![42]);
+        assert_eq!(
+            "0000X2".parse::<Mask>(),
+            Err(ParseErrorKind::InvalidMaskLength(6))
+        );
+    }
+
+    #[test]
+    fn test_docking_computer() {
+        let mut computer = DockingComputer::new(DecoderVersion::V1);
+        computer.run(&input_generator(SAMPLE_INPUT)).unwrap();
+        assert_eq!(computer.read(7), 101);
+        assert_eq!(computer.read(8), 64);
+        assert_eq!(computer.read(9), 0);
+        assert_eq!(computer.memory().len(), 2);
+
+        let mut computer = DockingComputer::new(DecoderVersion::V2);
+        computer.set_mask("00000000000000000000000000000000X0XX".parse().unwrap());
+        computer.write(26, 1).unwrap();
+        assert_eq!(computer.memory().len(), 8);
+        assert_eq!(computer.read(16), 1);
+        assert_eq!(computer.read(27), 1);
+        assert_eq!(computer.read(28), 0);
+        assert_eq!(computer.sum(), 8);
+    }
+
+    #[test]
+    fn test_docking_computer_floating_limit() {
+        let program = format!(
+            "mask = {}\nmem[8] = 11\nmask = {}\nmem[8] = 11",
+            "0".repeat(36),
+            "X".repeat(20) + &"0".repeat(16)
+        );
+        let program = parse_program(&program).unwrap();
+        let mut computer = DockingComputer::new(DecoderVersion::V2);
+        let error = computer.run(&program).unwrap_err();
+        assert_eq!(
+            error,
+            WriteError::TooManyFloatingBits {
+                address: 8,
+                floating: 20
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "mask has 20 floating bits for address 8, at most 16 can be expanded"
+        );
+        // The instructions before it did run
+        assert_eq!(computer.read(8), 11);
+
+        // Writing directly is refused just the same
+        computer.set_mask(program[1].mask);
+        assert!(computer.write(0, 1).is_err());
+        assert_eq!(computer.memory().len(), 1);
+
+        let mut computer = DockingComputer::new(DecoderVersion::V1);
+        assert_eq!(computer.run(&program), Ok(()));
+    }
+
+    #[test]
+    fn test_floating_memory() {
+        let ins = input_generator(SAMPLE_INPUT_2);
+        let memory = floating_memory(&ins);
+        let mut computer = DockingComputer::new(DecoderVersion::V2);
+        computer.run(&ins).unwrap();
+        assert_eq!(memory.len(), computer.memory().len() as u64);
+        for (&address, &value) in computer.memory() {
+            assert_eq!(memory.read(address), value);
+        }
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut computer = DockingComputer::new(DecoderVersion::V1);
+        computer.run(&input_generator(SAMPLE_INPUT)).unwrap();
+        assert_eq!(computer.dump(), vec![(7, 101), (8, 64)]);
+        assert_eq!(
+            computer.dump_text(),
+            "          7         101 000000000000000000000000000001100101
+          8          64 000000000000000000000000000001000000
+"
+        );
+        assert_eq!(
+            computer.dump_json(),
+            "[{\"address\":7,\"value\":101,\"binary\":\"000000000000000000000000000001100101\"},\
+             {\"address\":8,\"value\":64,\"binary\":\"000000000000000000000000000001000000\"}]"
+        );
+        assert_eq!(DockingComputer::new(DecoderVersion::V2).dump_json(), "[]");
+    }
+
+    #[test]
+    fn test_memory_diff() {
+        let diff = memory_diff(&input_generator(SAMPLE_INPUT_2));
+        assert_eq!(diff.len(), 5);
+        assert_eq!(
+            diff[0],
+            MemoryDiff {
+                addresses: AddressPattern::new(16, 0b1000),
+                v1: None,
+                v2: Some(1)
+            }
+        );
+        let v2_only: u64 = diff
+            .iter()
+            .filter(|d| d.v1.is_none())
+            .map(|d| d.addresses.size())
+            .sum();
+        assert_eq!(v2_only, 9);
+        // Both versions write 1 to address 26
+        assert!(diff.iter().all(|d| !d.addresses.contains(26)));
+        // The mask turns 100 into 0b110010
+        let v1_only = diff.iter().find(|d| d.addresses.contains(42)).unwrap();
+        assert_eq!(v1_only.v2, None);
+        assert_eq!(
+            v1_only.to_string(),
+            "000000000000000000000000000000101010          50           -"
+        );
+        assert_eq!(
+            diff[4].to_string(),
+            "00000000000000000000000000000011101X           -         100"
+        );
+
+        // Every address floats and both versions write 7 to address 5, which
+        // is split off without expanding the rest
+        let program = parse_program(&format!("mask = {}\nmem[5] = 7", "X".repeat(36))).unwrap();
+        let diff = memory_diff(&program);
+        assert_eq!(diff.len(), 36);
+        assert_eq!(
+            diff.iter().map(|d| d.addresses.size()).sum::<u64>(),
+            (1 << 36) - 1
+        );
+        assert!(diff.iter().all(|d| !d.addresses.contains(5)));
+    }
+
+    #[test]
+    fn test_parse_program() {
+        let program = "# sample program
+mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X
+
+mem[8]=11   # no spaces needed
+  mem[7] = 101
+mask = 000000000000000000000000000000X1001X
+mem[68719476735] = 68719476735
+";
+        let ins = parse_program(program).unwrap();
+        assert_eq!(ins.len(), 2);
+        assert_eq!((ins[0].line(), ins[1].line()), (2, 6));
+        assert_eq!(ins[0].mem, vec![(8, 11), (7, 101)]);
+        assert_eq!(ins[1].mem, vec![(WORD_MAX, WORD_MAX)]);
+    }
+
+    #[test]
+    fn test_parse_program_errors() {
+        let error = |input: &str| parse_program(input).unwrap_err();
+        let mask = "XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X";
+        assert_eq!(
+            error("mask = 01X"),
+            ParseError {
+                line: 1,
+                kind: ParseErrorKind::InvalidMaskLength(3)
+            }
+        );
+        assert_eq!(
+            error("mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXx0X").kind,
+            ParseErrorKind::InvalidMaskCharacter {
+                position: 33,
+                found: 'x'
+            }
+        );
+        assert_eq!(error("mem[8] = 11").kind, ParseErrorKind::MissingMask);
+        assert_eq!(
+            error(&format!("mask = {}\nme1m[2] = 3", mask)),
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::InvalidSyntax("me1m[2] = 3".to_string())
+            }
+        );
+        assert_eq!(error(&format!("mask = {}\n\nmem[-2] = 3", mask)).line, 3);
+        assert_eq!(
+            error(&format!("mask = {}\nmem[68719476736] = 3", mask)).kind,
+            ParseErrorKind::AddressOutOfRange("68719476736".to_string())
+        );
+        assert_eq!(
+            error(&format!("mask = {}\nmem[2] = 99999999999999999999", mask)).kind,
+            ParseErrorKind::ValueOutOfRange("99999999999999999999".to_string())
+        );
+        assert_eq!(
+            error("mask = 01X junk").to_string(),
+            "line 1: invalid instruction \"mask = 01X junk\""
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_floating_memory_matches_expansion(
+            writes in prop::collection::vec(
+                ("[01X]{8}", 0u64..256, 0u64..1000),
+                1..12,
+            ),
+        ) {
+            let program: Vec<Instruction> = writes
+                .iter()
+                .map(|(mask, address, value)| Instruction {
+                    mask: format!("{:0>36}", mask).parse().unwrap(),
+                    mem: vec![(*address, *value)],
+                    ..Instruction::default()
+                })
+                .collect();
+            let mut computer = DockingComputer::new(DecoderVersion::V2);
+            computer.run(&program).unwrap();
+            let memory = floating_memory(&program);
+            prop_assert_eq!(memory.sum(), computer.sum() as u128);
+            prop_assert_eq!(memory.len(), computer.memory().len() as u64);
+        }
+    }
 }
@@ -0,0 +1,248 @@
+//! Version 2 memory kept as address patterns instead of concrete addresses.
+//!
+//! A write with `k` floating bits touches `2^k` addresses. Rather than
+//! enumerating them, every write is stored as a pattern and older patterns
+//! are split so that no two stored patterns overlap. The number of stored
+//! patterns depends on how the writes overlap, not on the number of `X` bits.
+
+use super::WORD_BITS;
+use std::fmt;
+
+/// A set of addresses: the `floating` bits take any value, all others are
+/// fixed to the bits in `address`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AddressPattern {
+    address: u64,
+    floating: u64,
+}
+
+impl AddressPattern {
+    pub fn new(address: u64, floating: u64) -> Self {
+        AddressPattern {
+            address: address & !floating,
+            floating,
+        }
+    }
+
+    /// The lowest address matching the pattern
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    pub fn floating(&self) -> u64 {
+        self.floating
+    }
+
+    /// Number of addresses matching the pattern
+    pub fn size(&self) -> u64 {
+        1 << self.floating.count_ones()
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        address & !self.floating == self.address
+    }
+
+    pub fn overlaps(&self, other: &AddressPattern) -> bool {
+        let fixed = !self.floating & !other.floating;
+        (self.address ^ other.address) & fixed == 0
+    }
+
+    /// Disjoint patterns covering every address in `self` but not in `other`
+    pub fn subtract(&self, other: &AddressPattern) -> Vec<AddressPattern> {
+        if !self.overlaps(other) {
+            return vec![*self];
+        }
+        // Fix the bits that float here but not in `other` one at a time. The
+        // pieces that disagree with `other` on that bit are outside of it.
+        let mut pieces = Vec::new();
+        let mut rest = *self;
+        let mut split = self.floating & !other.floating;
+        while split != 0 {
+            let bit = split & split.wrapping_neg();
+            split &= !bit;
+            rest.floating &= !bit;
+            pieces.push(AddressPattern {
+                address: rest.address | (!other.address & bit),
+                floating: rest.floating,
+            });
+            rest.address |= other.address & bit;
+        }
+        pieces
+    }
+}
+
+/// Written like a mask, with an `X` for every floating bit
+impl fmt::Display for AddressPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for i in (0..WORD_BITS).rev() {
+            let c = if self.floating >> i & 1 == 1 {
+                'X'
+            } else if self.address >> i & 1 == 1 {
+                '1'
+            } else {
+                '0'
+            };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Memory written through address patterns, see the module documentation
+#[derive(Clone, Debug, Default)]
+pub struct FloatingMemory {
+    writes: Vec<(AddressPattern, u64)>,
+}
+
+impl FloatingMemory {
+    /// Writes `value` to every address in `pattern`, replacing older values
+    pub fn write(&mut self, pattern: AddressPattern, value: u64) {
+        self.writes = self
+            .writes
+            .iter()
+            .flat_map(|(old, v)| old.subtract(&pattern).into_iter().map(move |p| (p, *v)))
+            .collect();
+        self.writes.push((pattern, value));
+    }
+
+    /// Value at `address`, memory starts out as all zeros
+    pub fn read(&self, address: u64) -> u64 {
+        self.get(address).unwrap_or(0)
+    }
+
+    /// Value at `address`, `None` if it was never written
+    pub fn get(&self, address: u64) -> Option<u64> {
+        self.writes
+            .iter()
+            .find(|(pattern, _)| pattern.contains(address))
+            .map(|(_, value)| *value)
+    }
+
+    /// Number of addresses that have been written to
+    pub fn len(&self) -> u64 {
+        self.writes.iter().map(|(pattern, _)| pattern.size()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Sum of every value in memory, computed in 128 bits as every value can
+    /// be stored at up to 2^36 addresses
+    pub fn sum(&self) -> u128 {
+        self.writes
+            .iter()
+            .map(|(pattern, value)| pattern.size() as u128 * *value as u128)
+            .sum()
+    }
+
+    /// Every written `(pattern, value)`, sorted by the lowest address of the
+    /// pattern. The patterns don't overlap.
+    pub fn dump(&self) -> Vec<(AddressPattern, u64)> {
+        let mut writes = self.writes.clone();
+        writes.sort_unstable_by_key(|(pattern, _)| pattern.address);
+        writes
+    }
+
+    /// One line per pattern with the number of addresses and the value
+    pub fn dump_text(&self) -> String {
+        self.dump()
+            .iter()
+            .map(|(pattern, value)| format!("{} {:>11} {:>11}\n", pattern, pattern.size(), value))
+            .collect()
+    }
+
+    pub fn dump_json(&self) -> String {
+        let writes: Vec<String> = self
+            .dump()
+            .iter()
+            .map(|(pattern, value)| {
+                format!(
+                    "{{\"pattern\":\"{}\",\"addresses\":{},\"value\":{}}}",
+                    pattern,
+                    pattern.size(),
+                    value
+                )
+            })
+            .collect();
+        format!("[{}]", writes.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subtract() {
+        // 0X0X minus 00XX leaves 010X
+        let a = AddressPattern::new(0b0000, 0b0101);
+        let b = AddressPattern::new(0b0000, 0b0011);
+        assert_eq!(a.subtract(&b), vec![AddressPattern::new(0b0100, 0b0001)]);
+        assert_eq!(b.subtract(&a), vec![AddressPattern::new(0b0010, 0b0001)]);
+        assert_eq!(a.subtract(&a), vec![]);
+
+        let c = AddressPattern::new(0b1000, 0b0011);
+        assert!(!a.overlaps(&c));
+        assert_eq!(a.subtract(&c), vec![a]);
+
+        // XXX minus 101 leaves 7 addresses in 3 patterns
+        let all = AddressPattern::new(0, 0b111);
+        let pieces = all.subtract(&AddressPattern::new(0b101, 0));
+        assert_eq!(pieces.iter().map(AddressPattern::size).sum::<u64>(), 7);
+        assert_eq!(pieces.len(), 3);
+        assert!(pieces.iter().all(|p| !p.contains(0b101)));
+    }
+
+    #[test]
+    fn test_floating_memory() {
+        let mut memory = FloatingMemory::default();
+        assert!(memory.is_empty());
+        memory.write(AddressPattern::new(0b11010, 0b100001), 100);
+        memory.write(AddressPattern::new(0b10000, 0b1011), 1);
+        assert_eq!(memory.len(), 10);
+        assert_eq!(memory.sum(), 208);
+        assert_eq!(memory.read(0b11010), 1);
+        assert_eq!(memory.read(0b111011), 100);
+        assert_eq!(memory.read(0b100), 0);
+        assert_eq!(memory.get(0b100), None);
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut memory = FloatingMemory::default();
+        memory.write(AddressPattern::new(0b11010, 0b100001), 100);
+        memory.write(AddressPattern::new(0b10000, 0b1011), 1);
+        assert_eq!(
+            memory.dump(),
+            vec![
+                (AddressPattern::new(0b10000, 0b1011), 1),
+                (AddressPattern::new(0b111010, 0b1), 100)
+            ]
+        );
+        assert_eq!(
+            memory.dump_text(),
+            "00000000000000000000000000000001X0XX           8           1
+00000000000000000000000000000011101X           2         100
+"
+        );
+        assert_eq!(
+            memory.dump_json(),
+            "[{\"pattern\":\"00000000000000000000000000000001X0XX\",\"addresses\":8,\"value\":1},\
+             {\"pattern\":\"00000000000000000000000000000011101X\",\"addresses\":2,\"value\":100}]"
+        );
+        assert_eq!(FloatingMemory::default().dump_json(), "[]");
+    }
+
+    #[test]
+    fn test_floating_memory_wide() {
+        // Two fully floating 36-bit writes would be 2^37 inserts when expanded
+        let mut memory = FloatingMemory::default();
+        memory.write(AddressPattern::new(0, 0xFFFFFFFFF), 3);
+        memory.write(AddressPattern::new(0, 0xFFFFFFFFE), 5);
+        assert_eq!(memory.len(), 1 << 36);
+        assert_eq!(memory.sum(), 3 * (1 << 35) + 5 * (1 << 35));
+        assert_eq!(memory.read(1), 3);
+        assert_eq!(memory.read(2), 5);
+    }
+}
@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
 #[aoc_generator(day15)]
 pub fn input_generator(input: &str) -> Vec<usize> {
     input.split(',').map(|x| x.parse().unwrap()).collect()
@@ -5,31 +9,327 @@ pub fn input_generator(input: &str) -> Vec<usize> {
 
 #[aoc(day15, part1)]
 pub fn part1(data: &Vec<usize>) -> usize {
-    get_spoken(&data, 2020)
+    get_spoken(data, 2020).unwrap()
 }
 
 #[aoc(day15, part2)]
 pub fn part2(data: &Vec<usize>) -> usize {
-    get_spoken(&data, 30000000)
+    get_spoken(data, 30000000).unwrap()
+}
+
+/// Values below this are tracked in a flat array, larger ones in a map
+pub const DENSE_LIMIT: usize = 1 << 25;
+
+/// A `u32` per value, 0 for values that were never set. The array of small
+/// values grows as they are set, up to the dense limit.
+#[derive(Clone, Debug)]
+struct ValueMap {
+    dense_limit: usize,
+    dense: Vec<u32>,
+    sparse: HashMap<usize, u32>,
+}
+
+impl Default for ValueMap {
+    fn default() -> Self {
+        ValueMap {
+            dense_limit: DENSE_LIMIT,
+            dense: Vec::new(),
+            sparse: HashMap::default(),
+        }
+    }
 }
 
-fn get_spoken(data: &Vec<usize>, number: usize) -> usize {
-    let mut spoken: Vec<usize> = Vec::with_capacity(number);
-    // Initialize everything with 0
-    spoken.resize(number, 0);
-    for (i, v) in data.iter().enumerate() {
-        spoken[*v] = i + 1 as usize;
+impl ValueMap {
+    fn get(&self, value: usize) -> u32 {
+        if value < self.dense_limit {
+            self.dense.get(value).copied().unwrap_or(0)
+        } else {
+            self.sparse.get(&value).copied().unwrap_or(0)
+        }
+    }
+
+    fn set(&mut self, value: usize, x: u32) {
+        if value < self.dense_limit {
+            if value >= self.dense.len() {
+                let len = (value + 1).max(self.dense.len() * 2).min(self.dense_limit);
+                self.dense.resize(len, 0);
+            }
+            self.dense[value] = x;
+        } else {
+            self.sparse.insert(value, x);
+        }
     }
 
-    (data.len()..number).fold(*data.last().unwrap(), |last, index| {
-        let prev = spoken[last];
-        let current = match prev {
-            0 => 0,
-            _ => index - prev,
+    fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_u64(writer, self.dense_limit as u64)?;
+        write_u64(writer, self.dense.len() as u64)?;
+        for &x in &self.dense {
+            writer.write_all(&x.to_le_bytes())?;
+        }
+        write_u64(writer, self.sparse.len() as u64)?;
+        for (&value, &x) in &self.sparse {
+            write_u64(writer, value as u64)?;
+            writer.write_all(&x.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn restore<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let dense_limit = read_usize(reader)?;
+        let dense_len = read_usize(reader)?;
+        if dense_len > dense_limit {
+            return Err(invalid_data("dense array is past the dense limit"));
+        }
+        let dense = (0..dense_len)
+            .map(|_| read_u32(reader))
+            .collect::<io::Result<_>>()?;
+        let sparse_len = read_usize(reader)?;
+        let sparse = (0..sparse_len)
+            .map(|_| Ok((read_usize(reader)?, read_u32(reader)?)))
+            .collect::<io::Result<_>>()?;
+        Ok(ValueMap {
+            dense_limit,
+            dense,
+            sparse,
+        })
+    }
+
+    /// Every `(value, x)` that isn't 0, sorted by value
+    fn entries(&self) -> Vec<(usize, u32)> {
+        let mut sparse: Vec<(usize, u32)> = self.sparse.iter().map(|(&v, &x)| (v, x)).collect();
+        sparse.sort_unstable();
+        self.dense
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, x)| x != 0)
+            .chain(sparse)
+            .collect()
+    }
+}
+
+/// The memory game as a lazy iterator over the spoken numbers, starting with
+/// turn 1.
+///
+/// Turns are stored as `u32`, so the game stops after `u32::MAX` turns. The
+/// array of small values grows as they are spoken, up to the dense limit.
+///
+/// A clone is a checkpoint in memory, `save` and `restore` keep one across
+/// runs so that a long game can be extended later without replaying it.
+#[derive(Clone, Debug)]
+pub struct VanEck {
+    starting: Vec<usize>,
+    /// Turn each value was last spoken before the latest turn
+    last_turns: ValueMap,
+    /// Turns played so far
+    turn: u32,
+    last: usize,
+}
+
+impl VanEck {
+    pub fn new(starting: &[usize]) -> Self {
+        VanEck {
+            starting: starting.to_vec(),
+            last_turns: ValueMap::default(),
+            turn: 0,
+            last: 0,
+        }
+    }
+
+    /// Sets the first value kept in the map instead of the array
+    pub fn with_dense_limit(self, dense_limit: usize) -> Self {
+        VanEck {
+            last_turns: ValueMap {
+                dense_limit,
+                ..self.last_turns
+            },
+            ..self
+        }
+    }
+
+    /// Turns played so far
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+
+    /// The number spoken on the latest turn
+    pub fn current(&self) -> Option<usize> {
+        if self.turn == 0 {
+            None
+        } else {
+            Some(self.last)
+        }
+    }
+
+    /// Plays until `turn` and returns the number spoken then. A clone of the
+    /// game is a checkpoint that can be extended to later turns.
+    pub fn advance_to(&mut self, turn: u32) -> Option<usize> {
+        while self.turn < turn {
+            self.next()?;
+        }
+        self.current()
+    }
+
+    /// The turn `value` was last spoken before the latest turn
+    pub fn last_spoken(&self, value: usize) -> Option<u32> {
+        match self.last_turns.get(value) {
+            0 => None,
+            turn => Some(turn),
+        }
+    }
+
+    /// Writes the state of the game, wrap `writer` in a `BufWriter` as every
+    /// number is written on its own
+    pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_u64(writer, self.starting.len() as u64)?;
+        for &value in &self.starting {
+            write_u64(writer, value as u64)?;
+        }
+        writer.write_all(&self.turn.to_le_bytes())?;
+        write_u64(writer, self.last as u64)?;
+        self.last_turns.save(writer)
+    }
+
+    /// Reads a game written by `save`, it continues from the same turn
+    pub fn restore<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let starting_len = read_usize(reader)?;
+        let starting = (0..starting_len)
+            .map(|_| read_usize(reader))
+            .collect::<io::Result<_>>()?;
+        Ok(VanEck {
+            starting,
+            turn: read_u32(reader)?,
+            last: read_usize(reader)?,
+            last_turns: ValueMap::restore(reader)?,
+        })
+    }
+}
+
+fn write_u64<W: Write>(writer: &mut W, x: u64) -> io::Result<()> {
+    writer.write_all(&x.to_le_bytes())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_usize<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    usize::try_from(u64::from_le_bytes(bytes)).map_err(|_| invalid_data("value is too large"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Iterator for VanEck {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let turn = self.turn.checked_add(1)?;
+        let value = match self.starting.get(turn as usize - 1) {
+            Some(&value) => value,
+            None => match self.last_spoken(self.last) {
+                Some(previous) => (self.turn - previous) as usize,
+                None => 0,
+            },
         };
-        spoken[last] = index;
-        current
-    })
+        if self.turn > 0 {
+            self.last_turns.set(self.last, self.turn);
+        }
+        self.turn = turn;
+        self.last = value;
+        Some(value)
+    }
+}
+
+/// Two consecutive occurrences of the same number, `len` turns apart
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gap {
+    pub value: usize,
+    pub from: u32,
+    pub to: u32,
+}
+
+impl Gap {
+    pub fn len(&self) -> u32 {
+        self.to - self.from
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to == self.from
+    }
+}
+
+/// Statistics over every turn of a game that has been observed
+#[derive(Clone, Debug, Default)]
+pub struct GameStats {
+    first_turn: ValueMap,
+    frequency: ValueMap,
+    longest_gap: Option<Gap>,
+}
+
+impl GameStats {
+    /// Plays `game` until `turn`, recording every spoken number. Observe the
+    /// game from its first turn for the statistics to cover all of it.
+    pub fn observe(&mut self, game: &mut VanEck, turn: u32) {
+        while game.turn() < turn {
+            let value = match game.next() {
+                Some(value) => value,
+                None => return,
+            };
+            let turn = game.turn();
+            if self.first_turn.get(value) == 0 {
+                self.first_turn.set(value, turn);
+            }
+            self.frequency.set(value, self.frequency.get(value) + 1);
+            // The game has recorded every turn before this one
+            if let Some(from) = game.last_spoken(value) {
+                let gap = Gap {
+                    value,
+                    from,
+                    to: turn,
+                };
+                match self.longest_gap {
+                    Some(longest) if longest.len() >= gap.len() => {}
+                    _ => self.longest_gap = Some(gap),
+                }
+            }
+        }
+    }
+
+    /// The turn `value` was first spoken
+    pub fn first_turn(&self, value: usize) -> Option<u32> {
+        match self.first_turn.get(value) {
+            0 => None,
+            turn => Some(turn),
+        }
+    }
+
+    /// How many times `value` has been spoken
+    pub fn frequency(&self, value: usize) -> u32 {
+        self.frequency.get(value)
+    }
+
+    /// `(value, count)` for every spoken number, sorted by value
+    pub fn frequencies(&self) -> Vec<(usize, u32)> {
+        self.frequency.entries()
+    }
+
+    /// The longest time between a number being spoken twice, the earliest one
+    /// on ties
+    pub fn longest_gap(&self) -> Option<Gap> {
+        self.longest_gap
+    }
+}
+
+/// The number spoken on turn `number`, `None` for turn 0 and for turns the
+/// game doesn't reach (past `u32::MAX`)
+pub fn get_spoken(data: &[usize], number: usize) -> Option<usize> {
+    VanEck::new(data).nth(number.checked_sub(1)?)
 }
 
 #[cfg(test)]
@@ -62,4 +362,124 @@ mod tests {
         //assert_eq!(part2(&input_generator("3,2,1")), 18);
         //assert_eq!(part2(&input_generator("3,1,2")), 362);
     }
+
+    #[test]
+    fn test_van_eck() {
+        let game = VanEck::new(&[0, 3, 6]);
+        assert_eq!(
+            game.take(10).collect::<Vec<usize>>(),
+            vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0]
+        );
+
+        let mut game = VanEck::new(&[0, 3, 6]);
+        assert_eq!(game.current(), None);
+        assert_eq!(game.nth(8), Some(4));
+        assert_eq!((game.turn(), game.current()), (9, Some(4)));
+        assert_eq!(game.last_spoken(0), Some(8));
+        assert_eq!(game.last_spoken(4), None);
+        assert_eq!(game.last_spoken(6), Some(3));
+
+        // Turns are counted in a u32, the game ends after the last one
+        game.turn = u32::MAX;
+        assert_eq!(game.next(), None);
+        assert_eq!(game.turn(), u32::MAX);
+    }
+
+    #[test]
+    fn test_van_eck_starting_numbers() {
+        // Starting numbers beyond the target turn
+        assert_eq!(get_spoken(&[5000000000, 3], 2), Some(3));
+        assert_eq!(get_spoken(&[5000000000, 3, 5000000000], 4), Some(2));
+        assert_eq!(get_spoken(&[0, 3, 6], 2), Some(3));
+        assert_eq!(get_spoken(&[0, 3, 6], 0), None);
+        // Repeated starting numbers
+        assert_eq!(
+            VanEck::new(&[1, 1]).take(5).collect::<Vec<usize>>(),
+            vec![1, 1, 1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn test_advance_to() {
+        let mut game = VanEck::new(&[0, 3, 6]);
+        assert_eq!(game.advance_to(0), None);
+        assert_eq!(game.advance_to(10), Some(0));
+        let mut checkpoint = game.clone();
+        assert_eq!(game.advance_to(2020), Some(436));
+        // Going back isn't possible, the game stays where it is
+        assert_eq!(game.advance_to(9), Some(436));
+        assert_eq!(checkpoint.advance_to(2020), Some(436));
+        assert_eq!(checkpoint.advance_to(3000), get_spoken(&[0, 3, 6], 3000));
+    }
+
+    #[test]
+    fn test_save_restore() {
+        for dense_limit in [DENSE_LIMIT, 4].iter() {
+            let mut game = VanEck::new(&[0, 3, 6]).with_dense_limit(*dense_limit);
+            game.advance_to(2020);
+            let mut saved = Vec::new();
+            game.save(&mut saved).unwrap();
+            let mut restored = VanEck::restore(&mut saved.as_slice()).unwrap();
+            assert_eq!((restored.turn(), restored.current()), (2020, Some(436)));
+            assert_eq!(restored.last_spoken(0), game.last_spoken(0));
+            assert_eq!(restored.advance_to(3000), game.advance_to(3000));
+        }
+
+        // A game that hasn't started yet still plays the starting numbers
+        let mut saved = Vec::new();
+        VanEck::new(&[0, 3, 6]).save(&mut saved).unwrap();
+        let restored = VanEck::restore(&mut saved.as_slice()).unwrap();
+        assert_eq!(
+            restored.take(10).collect::<Vec<usize>>(),
+            vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0]
+        );
+
+        saved.truncate(saved.len() - 1);
+        let error = VanEck::restore(&mut saved.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_game_stats() {
+        // 0, 3, 6, 0, 3, 3, 1, 0, 4, 0
+        let mut game = VanEck::new(&[0, 3, 6]);
+        let mut stats = GameStats::default();
+        stats.observe(&mut game, 10);
+        assert_eq!(stats.first_turn(3), Some(2));
+        assert_eq!(stats.first_turn(4), Some(9));
+        assert_eq!(stats.first_turn(2), None);
+        assert_eq!(stats.frequency(0), 4);
+        assert_eq!(
+            stats.frequencies(),
+            vec![(0, 4), (1, 1), (3, 3), (4, 1), (6, 1)]
+        );
+        assert_eq!(
+            stats.longest_gap(),
+            Some(Gap {
+                value: 0,
+                from: 4,
+                to: 8
+            })
+        );
+
+        // Resuming keeps the statistics of the earlier turns
+        stats.observe(&mut game, 2020);
+        assert_eq!(game.current(), Some(436));
+        assert_eq!(
+            stats.frequencies().iter().map(|(_, c)| c).sum::<u32>(),
+            2020
+        );
+        assert_eq!(stats.first_turn(6), Some(3));
+    }
+
+    #[test]
+    fn test_van_eck_sparse() {
+        let dense: Vec<usize> = VanEck::new(&[3, 1, 2]).take(2020).collect();
+        let sparse: Vec<usize> = VanEck::new(&[3, 1, 2])
+            .with_dense_limit(4)
+            .take(2020)
+            .collect();
+        assert_eq!(dense, sparse);
+        assert_eq!(sparse[2019], 1836);
+    }
 }
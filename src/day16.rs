@@ -1,147 +1,746 @@
-use itertools::Itertools;
-
-// (start1, end1), (start2, end2), Rule-name
-// start and end are inclusive
-type RuleRange = ((usize, usize), (usize, usize), String);
-#[derive(Clone)]
-struct Instruction {
-    rules: Vec<RuleRange>,
+use std::collections::HashMap;
+use std::fmt;
+
+/// Header of the section with my ticket
+pub const MY_TICKET_HEADER: &str = "your ticket:";
+/// Header of the section with the nearby tickets
+pub const NEARBY_TICKETS_HEADER: &str = "nearby tickets:";
+
+/// Errors from parsing the notes, line numbers start at 1
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    MissingName(String),
+    InvalidRange(String),
+    NoRules,
+    /// A section header that isn't in the notes
+    MissingSection(&'static str),
+    DuplicateSection(&'static str),
+    UnexpectedLine {
+        line: usize,
+        text: String,
+    },
+    InvalidNumber {
+        line: usize,
+        token: String,
+    },
+    /// A ticket with a different number of fields than there are rules
+    FieldCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingName(s) => write!(f, "rule without a name {:?}", s),
+            Self::InvalidRange(s) => write!(f, "invalid range {:?}", s),
+            Self::NoRules => write!(f, "no rules"),
+            Self::MissingSection(header) => write!(f, "missing section {:?}", header),
+            Self::DuplicateSection(header) => write!(f, "duplicate section {:?}", header),
+            Self::UnexpectedLine { line, text } => {
+                write!(f, "line {}: unexpected {:?}", line, text)
+            }
+            Self::InvalidNumber { line, token } => {
+                write!(f, "line {}: invalid number {:?}", line, token)
+            }
+            Self::FieldCount {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: ticket has {} fields, expected {}",
+                line, found, expected
+            ),
+        }
+    }
+}
+
+/// Errors from assigning fields to ticket columns
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssignmentError {
+    /// Too few fields fit these columns
+    Unsatisfiable { columns: Vec<usize> },
+    /// These columns get different fields in different assignments
+    Ambiguous { columns: Vec<usize> },
+    /// `ticket` (an index into the given tickets) doesn't have one value per
+    /// rule
+    InvalidTicketWidth {
+        ticket: usize,
+        found: usize,
+        expected: usize,
+    },
+}
+
+impl fmt::Display for AssignmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unsatisfiable { columns } => {
+                write!(f, "not enough fields fit columns {:?}", columns)
+            }
+            Self::Ambiguous { columns } => write!(f, "columns {:?} are ambiguous", columns),
+            Self::InvalidTicketWidth {
+                ticket,
+                found,
+                expected,
+            } => write!(
+                f,
+                "ticket {} has {} values, expected {}",
+                ticket, found, expected
+            ),
+        }
+    }
+}
+
+/// A ticket field and the inclusive ranges of values valid for it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    name: String,
+    /// Sorted, without overlapping or adjacent ranges
+    ranges: Vec<(usize, usize)>,
+}
+
+impl Rule {
+    /// Merges overlapping and adjacent `ranges`
+    pub fn new<I: IntoIterator<Item = (usize, usize)>>(name: &str, ranges: I) -> Self {
+        let mut sorted: Vec<(usize, usize)> = ranges.into_iter().collect();
+        sorted.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(sorted.len());
+        for (start, end) in sorted {
+            match merged.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        Rule {
+            name: name.to_string(),
+            ranges: merged,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sorted, without overlapping or adjacent ranges
+    pub fn ranges(&self) -> &[(usize, usize)] {
+        &self.ranges
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        let i = self.ranges.partition_point(|&(_, end)| end < value);
+        matches!(self.ranges.get(i), Some(&(start, _)) if start <= value)
+    }
+}
+
+// `name: 1-3 or 5-7`, with any number of ranges
+impl std::str::FromStr for Rule {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut data = s.splitn(2, ':');
+        let name = data.next().unwrap().trim();
+        let ranges = match data.next() {
+            Some(ranges) if !name.is_empty() => ranges,
+            _ => return Err(ParseError::MissingName(s.to_string())),
+        };
+        let ranges = ranges
+            .split(" or ")
+            .map(|r| {
+                let r = r.trim();
+                let mut bounds = r.splitn(2, '-').map(|x| x.parse::<usize>());
+                match (bounds.next(), bounds.next()) {
+                    (Some(Ok(start)), Some(Ok(end))) if start <= end => Ok((start, end)),
+                    _ => Err(ParseError::InvalidRange(r.to_string())),
+                }
+            })
+            .collect::<Result<Vec<(usize, usize)>, ParseError>>()?;
+        Ok(Rule::new(name, ranges))
+    }
+}
+
+/// The notes: the field rules, my ticket and the nearby tickets
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    rules: Vec<Rule>,
     my_ticket: Vec<usize>,
     nearby_tickets: Vec<Vec<usize>>,
 }
 
+impl Instruction {
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn my_ticket(&self) -> &[usize] {
+        &self.my_ticket
+    }
+
+    pub fn nearby_tickets(&self) -> &[Vec<usize>] {
+        &self.nearby_tickets
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Rules,
+    MyTicket,
+    NearbyTickets,
+}
+
+// Comma separated ticket values with as many fields as there are rules
+fn parse_ticket(text: &str, line: usize, fields: usize) -> Result<Vec<usize>, ParseError> {
+    let ticket = text
+        .split(',')
+        .map(|token| {
+            let token = token.trim();
+            token.parse().map_err(|_| ParseError::InvalidNumber {
+                line,
+                token: token.to_string(),
+            })
+        })
+        .collect::<Result<Vec<usize>, ParseError>>()?;
+    if ticket.len() != fields {
+        return Err(ParseError::FieldCount {
+            line,
+            expected: fields,
+            found: ticket.len(),
+        });
+    }
+    Ok(ticket)
+}
+
+/// Rules come first, followed by the sections starting with
+/// `MY_TICKET_HEADER` and `NEARBY_TICKETS_HEADER` in any order. Blank lines
+/// and surrounding whitespace, including `\r`, are ignored.
+impl std::str::FromStr for Instruction {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules: Vec<Rule> = Vec::new();
+        let mut my_ticket: Option<Vec<usize>> = None;
+        let mut nearby_tickets: Option<Vec<Vec<usize>>> = None;
+        let mut section = Section::Rules;
+
+        for (i, text) in s.lines().enumerate() {
+            let line = i + 1;
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            if text == MY_TICKET_HEADER || text == NEARBY_TICKETS_HEADER {
+                let (next, seen, header) = if text == MY_TICKET_HEADER {
+                    (Section::MyTicket, my_ticket.is_some(), MY_TICKET_HEADER)
+                } else {
+                    (
+                        Section::NearbyTickets,
+                        nearby_tickets.is_some(),
+                        NEARBY_TICKETS_HEADER,
+                    )
+                };
+                if seen || section == next {
+                    return Err(ParseError::DuplicateSection(header));
+                }
+                if rules.is_empty() {
+                    return Err(ParseError::NoRules);
+                }
+                section = next;
+                if section == Section::NearbyTickets {
+                    nearby_tickets = Some(Vec::new());
+                }
+                continue;
+            }
+            match section {
+                Section::Rules => rules.push(text.parse()?),
+                Section::MyTicket if my_ticket.is_none() => {
+                    my_ticket = Some(parse_ticket(text, line, rules.len())?)
+                }
+                Section::MyTicket => {
+                    return Err(ParseError::UnexpectedLine {
+                        line,
+                        text: text.to_string(),
+                    })
+                }
+                Section::NearbyTickets => {
+                    nearby_tickets
+                        .as_mut()
+                        .unwrap()
+                        .push(parse_ticket(text, line, rules.len())?)
+                }
+            }
+        }
+
+        if rules.is_empty() {
+            return Err(ParseError::NoRules);
+        }
+        Ok(Instruction {
+            rules,
+            my_ticket: my_ticket.ok_or(ParseError::MissingSection(MY_TICKET_HEADER))?,
+            nearby_tickets: nearby_tickets
+                .ok_or(ParseError::MissingSection(NEARBY_TICKETS_HEADER))?,
+        })
+    }
+}
+
 #[aoc_generator(day16)]
-fn input_generator(input: &str) -> Instruction {
-    let parts: Vec<&str> = input.splitn(3, "\n\n").collect();
+pub fn input_generator(input: &str) -> Instruction {
+    input.parse().unwrap()
+}
 
-    let mut rules: Vec<RuleRange> = Vec::new();
-    for line in parts[0].lines() {
-        let mut data = line.split(':');
-        let rule_name = data.next().unwrap();
+/// Sum of the values on nearby tickets that fit no rule
+#[aoc(day16, part1)]
+pub fn part1(instruction: &Instruction) -> usize {
+    scan_tickets(instruction)
+        .iter()
+        .flat_map(|report| report.invalid_values())
+        .map(|(_, value)| value)
+        .sum::<usize>()
+}
 
-        let ranges: Vec<Vec<usize>> = data
-            .next()
-            .unwrap()
-            .split(" or ")
-            .map(|r| r.trim().split('-').map(|x| x.parse().unwrap()).collect())
+/// Fields multiplied for part 2
+pub const PART2_PREFIX: &str = "departure";
+
+/// Product of my ticket's fields starting with `PART2_PREFIX`
+#[aoc(day16, part2)]
+pub fn part2(instruction: &Instruction) -> usize {
+    let (my_ticket, _) = decode_tickets(instruction).unwrap();
+    my_ticket
+        .with_prefix(PART2_PREFIX)
+        .map(|(_, value)| value)
+        .product()
+}
+
+/// A ticket's values keyed by field name, in column order
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedTicket {
+    fields: Vec<(String, usize)>,
+}
+
+impl DecodedTicket {
+    /// Pairs the field names in column order with the ticket's values
+    pub fn new(order: &[String], values: &[usize]) -> Self {
+        DecodedTicket {
+            fields: order.iter().cloned().zip(values.iter().copied()).collect(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| *v)
+    }
+
+    /// Fields whose name starts with `prefix`
+    pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, usize)> {
+        self.fields
+            .iter()
+            .filter(move |(name, _)| name.starts_with(prefix))
+            .map(|(name, value)| (name.as_str(), *value))
+    }
+
+    /// The fields without their column order
+    pub fn to_map(&self) -> HashMap<String, usize> {
+        self.fields.iter().cloned().collect()
+    }
+
+    /// A JSON object in column order
+    pub fn to_json(&self) -> String {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(name, value)| format!("{}:{}", json_string(name), value))
             .collect();
-        rules.push((
-            (ranges[0][0], ranges[0][1]),
-            (ranges[1][0], ranges[1][1]),
-            rule_name.to_owned(),
-        ));
+        format!("{{{}}}", fields.join(","))
     }
+}
 
-    let ticket_parser = |line: &str| {
-        line.split(',')
-            .map(|x| x.parse::<usize>().unwrap())
-            .collect::<Vec<usize>>()
-    };
+/// CSV with a header of field names, tickets must share the field order
+pub fn tickets_to_csv(tickets: &[DecodedTicket]) -> String {
+    let mut csv = String::new();
+    if let Some(first) = tickets.first() {
+        let header: Vec<String> = first.fields.iter().map(|(n, _)| csv_field(n)).collect();
+        csv.push_str(&header.join(","));
+        csv.push('\n');
+    }
+    for ticket in tickets {
+        let values: Vec<String> = ticket.fields.iter().map(|(_, v)| v.to_string()).collect();
+        csv.push_str(&values.join(","));
+        csv.push('\n');
+    }
+    csv
+}
 
-    let my_ticket = parts[1].lines().skip(1).map(ticket_parser).next().unwrap();
-    let nearby_tickets = parts[2].lines().skip(1).map(ticket_parser).collect();
+fn json_string(s: &str) -> String {
+    let mut json = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
 
-    Instruction {
-        rules: rules.to_owned(),
-        my_ticket: my_ticket,
-        nearby_tickets: nearby_tickets,
+fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
     }
 }
 
-#[aoc(day16, part1)]
-fn part1(instruction: &Instruction) -> usize {
+// The nearby tickets `scan_tickets` doesn't drop
+fn valid_tickets(instruction: &Instruction) -> Vec<&[usize]> {
+    scan_tickets(instruction)
+        .iter()
+        .zip(&instruction.nearby_tickets)
+        .filter(|(report, _)| report.dropped.is_none())
+        .map(|(_, ticket)| ticket.as_slice())
+        .collect()
+}
+
+/// Field names in column order, using the valid nearby tickets
+pub fn field_order(instruction: &Instruction) -> Result<Vec<String>, AssignmentError> {
+    map_columns(&instruction.rules, &valid_tickets(instruction))
+}
+
+/// Decodes my ticket and every valid nearby ticket
+pub fn decode_tickets(
+    instruction: &Instruction,
+) -> Result<(DecodedTicket, Vec<DecodedTicket>), AssignmentError> {
+    let valid = valid_tickets(instruction);
+    let order = map_columns(&instruction.rules, &valid)?;
+    let nearby = valid
+        .iter()
+        .map(|ticket| DecodedTicket::new(&order, ticket))
+        .collect();
+    Ok((DecodedTicket::new(&order, &instruction.my_ticket), nearby))
+}
+
+/// A value on a nearby ticket and the rules it satisfies
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueCheck {
+    pub column: usize,
+    pub value: usize,
+    /// Names of the rules the value fits
+    pub rules: Vec<String>,
+}
+
+/// Why a nearby ticket isn't used to assign fields
+#[derive(Clone, Debug, PartialEq)]
+pub enum DropReason {
+    /// `(column, value)` of every value that fits no rule
+    InvalidValues(Vec<(usize, usize)>),
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidValues(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|(column, value)| format!("{} in column {} fits no rule", value, column))
+                    .collect();
+                write!(f, "{}", values.join(", "))
+            }
+        }
+    }
+}
+
+/// The result of scanning one nearby ticket
+#[derive(Clone, Debug, PartialEq)]
+pub struct TicketReport {
+    /// Index among the nearby tickets
+    pub ticket: usize,
+    pub values: Vec<ValueCheck>,
+    /// `None` if the ticket is used to assign fields
+    pub dropped: Option<DropReason>,
+}
+
+impl TicketReport {
+    /// `(column, value)` of every value that fits no rule
+    pub fn invalid_values(&self) -> Vec<(usize, usize)> {
+        self.values
+            .iter()
+            .filter(|check| check.rules.is_empty())
+            .map(|check| (check.column, check.value))
+            .collect()
+    }
+}
+
+impl fmt::Display for TicketReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.dropped {
+            Some(reason) => writeln!(f, "ticket {}: dropped, {}", self.ticket, reason)?,
+            None => writeln!(f, "ticket {}: valid", self.ticket)?,
+        }
+        for check in &self.values {
+            let rules = if check.rules.is_empty() {
+                "no rule".to_string()
+            } else {
+                check.rules.join(", ")
+            };
+            writeln!(
+                f,
+                "  column {}: {} fits {}",
+                check.column, check.value, rules
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks every value of the nearby tickets against all rules
+pub fn scan_tickets(instruction: &Instruction) -> Vec<TicketReport> {
     instruction
         .nearby_tickets
         .iter()
-        .flat_map(|ticket| ticket.iter())
-        .filter_map(|n| {
-            if !instruction
-                .rules
+        .enumerate()
+        .map(|(ticket, values)| {
+            let values: Vec<ValueCheck> = values
                 .iter()
-                .any(|((start1, end1), (start2, end2), _)| {
-                    within_range(n, start1, end1, start2, end2)
+                .enumerate()
+                .map(|(column, &value)| ValueCheck {
+                    column,
+                    value,
+                    rules: instruction
+                        .rules
+                        .iter()
+                        .filter(|rule| rule.contains(value))
+                        .map(|rule| rule.name.to_owned())
+                        .collect(),
                 })
-            {
-                return Some(n);
+                .collect();
+            let mut report = TicketReport {
+                ticket,
+                values,
+                dropped: None,
+            };
+            let invalid = report.invalid_values();
+            if !invalid.is_empty() {
+                report.dropped = Some(DropReason::InvalidValues(invalid));
             }
-            None
+            report
         })
-        .sum::<usize>()
+        .collect()
 }
 
-#[aoc(day16, part2)]
-fn part2(instruction: &Instruction) -> usize {
-    let filtered: Vec<Vec<usize>> = instruction
-        .nearby_tickets
-        .clone()
-        .into_iter()
-        .filter(|ticket| {
-            ticket.iter().all(|n| {
-                instruction
-                    .rules
-                    .iter()
-                    .any(|rule| within_range(n, &rule.0 .0, &rule.0 .1, &rule.1 .0, &rule.1 .1))
-            })
-        })
+/// Calculates and maps each column from `tickets` to a specific rule-name.
+/// The returned vector is ordered. Every ticket must have a value for each
+/// rule.
+pub fn map_columns<T: AsRef<[usize]>>(
+    rules: &[Rule],
+    tickets: &[T],
+) -> Result<Vec<String>, AssignmentError> {
+    let candidates = candidates(rules, tickets)?;
+    let matching = perfect_matching(&candidates, rules.len())?;
+    let columns: Vec<usize> = (0..candidates.len())
+        .filter(|&c| has_alternative(&candidates, &matching, rules.len(), c))
         .collect();
-    let ins = Instruction {
-        nearby_tickets: filtered,
-        ..(&instruction.clone()).to_owned()
-    };
-    let ordered = map_columns(&ins);
+    if !columns.is_empty() {
+        return Err(AssignmentError::Ambiguous { columns });
+    }
+    Ok(matching
+        .into_iter()
+        .map(|r| rules[r].name.to_owned())
+        .collect())
+}
 
-    let f: Vec<usize> = ordered
+// The rules that every ticket satisfies, for each column
+fn candidates<T: AsRef<[usize]>>(
+    rules: &[Rule],
+    tickets: &[T],
+) -> Result<Vec<Vec<usize>>, AssignmentError> {
+    if let Some((ticket, values)) = tickets
         .iter()
+        .map(AsRef::as_ref)
         .enumerate()
-        .filter(|(_, v)| v.starts_with("departure"))
-        .map(|(i, _)| ins.my_ticket[i])
-        .collect();
-    f.iter().product()
+        .find(|(_, values)| values.len() != rules.len())
+    {
+        return Err(AssignmentError::InvalidTicketWidth {
+            ticket,
+            found: values.len(),
+            expected: rules.len(),
+        });
+    }
+    Ok((0..rules.len())
+        .map(|column| {
+            (0..rules.len())
+                .filter(|&r| {
+                    tickets
+                        .iter()
+                        .all(|ticket| rules[r].contains(ticket.as_ref()[column]))
+                })
+                .collect()
+        })
+        .collect())
 }
 
-// Calculates and maps each column from nearby_tickets to a specific rule-name.
-// The returned vector is ordered
-fn map_columns(instruction: &Instruction) -> Vec<String> {
-    let mut columns = Vec::new();
+/// Ways to give each column a different rule, as the rule index for each
+/// column, stopping after `limit` of them. Errors with a set of columns that
+/// have fewer candidate rules between them than there are columns if there is
+/// no way.
+pub fn all_assignments<T: AsRef<[usize]>>(
+    rules: &[Rule],
+    tickets: &[T],
+    limit: usize,
+) -> Result<Vec<Vec<usize>>, AssignmentError> {
+    let candidates = candidates(rules, tickets)?;
+    perfect_matching(&candidates, rules.len())?;
 
-    for rule in instruction.rules.iter() {
-        for j in 0..instruction.my_ticket.len() {
-            let matches = instruction
-                .nearby_tickets
-                .iter()
-                .all(|x| within_range(&x[j], &rule.0 .0, &rule.0 .1, &rule.1 .0, &rule.1 .1));
-            if matches {
-                columns.push((j, rule.2.to_owned()));
+    let mut assignments = Vec::new();
+    let mut current = vec![None; candidates.len()];
+    let mut used = vec![false; rules.len()];
+    backtrack(
+        &candidates,
+        &mut current,
+        &mut used,
+        &mut assignments,
+        limit,
+    );
+    Ok(assignments)
+}
+
+// A rule for every column, or the columns that can't all get one
+fn perfect_matching(
+    candidates: &[Vec<usize>],
+    rules: usize,
+) -> Result<Vec<usize>, AssignmentError> {
+    let matching = max_matching(candidates, rules);
+    match matching.iter().position(Option::is_none) {
+        Some(column) => Err(AssignmentError::Unsatisfiable {
+            columns: hall_violator(candidates, &matching, column),
+        }),
+        None => Ok(matching.into_iter().map(Option::unwrap).collect()),
+    }
+}
+
+// Finds a path from `column` to a free rule that alternates between unmatched
+// and matched edges, and flips it
+fn augment(
+    column: usize,
+    candidates: &[Vec<usize>],
+    owner: &mut Vec<Option<usize>>,
+    seen: &mut Vec<bool>,
+) -> bool {
+    for &r in &candidates[column] {
+        if !seen[r] {
+            seen[r] = true;
+            let free = match owner[r] {
+                Some(c) => augment(c, candidates, owner, seen),
+                None => true,
+            };
+            if free {
+                owner[r] = Some(column);
+                return true;
             }
         }
     }
+    false
+}
 
-    let mut order: Vec<String> = Vec::with_capacity(instruction.rules.len());
-    order.resize(instruction.rules.len(), String::new());
-    loop {
-        if columns.len() == 0 {
-            break;
+// Maximum bipartite matching of columns to rules with augmenting paths
+fn max_matching(candidates: &[Vec<usize>], rules: usize) -> Vec<Option<usize>> {
+    let mut owner: Vec<Option<usize>> = vec![None; rules];
+    for column in 0..candidates.len() {
+        augment(column, candidates, &mut owner, &mut vec![false; rules]);
+    }
+    let mut matching = vec![None; candidates.len()];
+    for (r, column) in owner.iter().enumerate() {
+        if let Some(c) = column {
+            matching[*c] = Some(r);
         }
-        let map = columns.clone().into_iter().into_group_map();
+    }
+    matching
+}
 
-        for (k, v) in map {
-            if v.len() == 1 {
-                let name = v[0].clone();
-                order[k] = v[0].to_owned();
-                columns = columns
-                    .clone()
-                    .into_iter()
-                    .filter(|(_, v)| *v != name)
-                    .collect();
+// Whether another perfect matching gives `column` a different rule. Without
+// its current rule the column needs an augmenting path, which either ends at
+// that rule again (an alternating cycle) or at a rule no column uses.
+fn has_alternative(
+    candidates: &[Vec<usize>],
+    matching: &[usize],
+    rules: usize,
+    column: usize,
+) -> bool {
+    let mut owner: Vec<Option<usize>> = vec![None; rules];
+    for (c, &r) in matching.iter().enumerate() {
+        owner[r] = Some(c);
+    }
+    owner[matching[column]] = None;
+    let mut seen = vec![false; rules];
+    candidates[column]
+        .iter()
+        .filter(|&&r| r != matching[column])
+        .any(|&r| {
+            if seen[r] {
+                return false;
+            }
+            seen[r] = true;
+            match owner[r] {
+                Some(c) => augment(c, candidates, &mut owner, &mut seen),
+                None => true,
+            }
+        })
+}
+
+// The columns reachable from an unmatched column by alternating paths. Their
+// candidates are all matched within the set, so there's one rule too few.
+fn hall_violator(
+    candidates: &[Vec<usize>],
+    matching: &[Option<usize>],
+    column: usize,
+) -> Vec<usize> {
+    let mut visited = vec![false; candidates.len()];
+    visited[column] = true;
+    let mut stack = vec![column];
+    while let Some(c) = stack.pop() {
+        for &r in &candidates[c] {
+            if let Some(next) = matching.iter().position(|&m| m == Some(r)) {
+                if !visited[next] {
+                    visited[next] = true;
+                    stack.push(next);
+                }
             }
         }
     }
-
-    order
+    (0..candidates.len()).filter(|&c| visited[c]).collect()
 }
 
-fn within_range(value: &usize, start1: &usize, end1: &usize, start2: &usize, end2: &usize) -> bool {
-    (value >= start1 && value <= end1) || (value >= start2 && value <= end2)
+// Assigns the column with the fewest free rules first, until there are
+// `limit` assignments
+fn backtrack(
+    candidates: &[Vec<usize>],
+    current: &mut Vec<Option<usize>>,
+    used: &mut Vec<bool>,
+    assignments: &mut Vec<Vec<usize>>,
+    limit: usize,
+) {
+    let free = |column: usize| candidates[column].iter().filter(|&&r| !used[r]);
+    let column = (0..candidates.len())
+        .filter(|&c| current[c].is_none())
+        .min_by_key(|&c| free(c).count());
+    let column = match column {
+        Some(column) => column,
+        None => {
+            assignments.push(current.iter().map(|r| r.unwrap()).collect());
+            return;
+        }
+    };
+    let options: Vec<usize> = free(column).copied().collect();
+    for r in options {
+        if assignments.len() >= limit {
+            return;
+        }
+        current[column] = Some(r);
+        used[r] = true;
+        backtrack(candidates, current, used, assignments, limit);
+        used[r] = false;
+        current[column] = None;
+    }
 }
 
 #[cfg(test)]
@@ -167,9 +766,9 @@ nearby tickets:
         assert_eq!(
             ins.rules,
             vec![
-                ((1, 3), (5, 7), "class".to_owned()),
-                ((6, 11), (33, 44), "row".to_owned()),
-                ((13, 40), (45, 50), "seat".to_owned()),
+                Rule::new("class", vec![(1, 3), (5, 7)]),
+                Rule::new("row", vec![(6, 11), (33, 44)]),
+                Rule::new("seat", vec![(13, 40), (45, 50)]),
             ]
         );
         assert_eq!(ins.my_ticket, vec![7, 1, 14]);
@@ -204,10 +803,287 @@ nearby tickets:
 15,1,5
 5,14,9";
         let ins = input_generator(input);
-        let ordered = map_columns(&ins);
+        let ordered = map_columns(&ins.rules, &ins.nearby_tickets).unwrap();
         assert_eq!(
             &ordered,
             &vec!["row".to_owned(), "class".to_owned(), "seat".to_owned()]
         );
     }
+
+    #[test]
+    fn test_parser_layout() {
+        let crlf = SAMPLE_INPUT.replace('\n', "\r\n");
+        let ins: Instruction = crlf.parse().unwrap();
+        assert_eq!(ins.rules().len(), 3);
+        assert_eq!(ins.my_ticket(), &[7, 1, 14]);
+        assert_eq!(ins.nearby_tickets().len(), 4);
+
+        let spaced = "\n\nclass: 1-3 or 5-7\n\n\nrow: 6-11\n\nnearby tickets:\n\n7,3\n\n40,4\n\n \
+                      your ticket:\n7,1\n\n\n";
+        let ins: Instruction = spaced.parse().unwrap();
+        assert_eq!(ins.rules()[1].name(), "row");
+        assert_eq!(ins.rules()[1].ranges(), &[(6, 11)]);
+        assert_eq!(ins.my_ticket(), &[7, 1]);
+        assert_eq!(ins.nearby_tickets(), &[vec![7, 3], vec![40, 4]]);
+
+        let ins: Instruction = "a: 1-3\nyour ticket:\n1\nnearby tickets:".parse().unwrap();
+        assert!(ins.nearby_tickets().is_empty());
+    }
+
+    #[test]
+    fn test_parser_errors() {
+        let error = |input: &str| input.parse::<Instruction>().unwrap_err();
+        assert_eq!(
+            error("a: 1-3\n\nyour ticket:\n1"),
+            ParseError::MissingSection(NEARBY_TICKETS_HEADER)
+        );
+        assert_eq!(
+            error("a: 1-3\nnearby tickets:\n1"),
+            ParseError::MissingSection(MY_TICKET_HEADER)
+        );
+        assert_eq!(error("your ticket:\n1"), ParseError::NoRules);
+        assert_eq!(
+            error("a: 1-3\nyour ticket:\n1\nyour ticket:\n2"),
+            ParseError::DuplicateSection(MY_TICKET_HEADER)
+        );
+        assert_eq!(
+            error("a: 1-3\nyour ticket:\n1\n2\nnearby tickets:"),
+            ParseError::UnexpectedLine {
+                line: 4,
+                text: "2".to_string()
+            }
+        );
+        assert_eq!(
+            error("a: 1-3\nyour ticket:\n1\nnearby tickets:\n2\n3,x"),
+            ParseError::InvalidNumber {
+                line: 6,
+                token: "x".to_string()
+            }
+        );
+        let err = error("a: 1-3\nb: 4-5\nyour ticket:\n1,2\nnearby tickets:\n1,2\n\n1,2,3");
+        assert_eq!(
+            err,
+            ParseError::FieldCount {
+                line: 8,
+                expected: 2,
+                found: 3
+            }
+        );
+        assert_eq!(err.to_string(), "line 8: ticket has 3 fields, expected 2");
+        assert_eq!(
+            error("a 1-3\nyour ticket:\n1\nnearby tickets:"),
+            ParseError::MissingName("a 1-3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_scan_tickets() {
+        let reports = scan_tickets(&input_generator(SAMPLE_INPUT));
+        assert_eq!(reports.len(), 4);
+        assert_eq!(reports[0].dropped, None);
+        assert_eq!(
+            reports[0].values[1],
+            ValueCheck {
+                column: 1,
+                value: 3,
+                rules: vec!["class".to_owned()]
+            }
+        );
+        assert_eq!(
+            reports[2].dropped,
+            Some(DropReason::InvalidValues(vec![(0, 55)]))
+        );
+        assert_eq!(reports[3].invalid_values(), vec![(2, 12)]);
+        assert_eq!(
+            reports[1].to_string(),
+            "ticket 1: dropped, 4 in column 1 fits no rule
+  column 0: 40 fits row, seat
+  column 1: 4 fits no rule
+  column 2: 50 fits seat
+"
+        );
+    }
+
+    #[test]
+    fn test_decode_tickets() {
+        let input = "class: 0-1 or 4-19
+departure row: 0-5 or 8-19
+departure \"seat\", 2: 0-13 or 16-19
+
+your ticket:
+11,12,13
+
+nearby tickets:
+3,9,18
+15,1,5
+5,14,9
+20,1,1";
+        let ins = input_generator(input);
+        let (mine, nearby) = decode_tickets(&ins).unwrap();
+        assert_eq!(mine.get("class"), Some(12));
+        assert_eq!(mine.get("departure row"), Some(11));
+        assert_eq!(mine.get("row"), None);
+        assert_eq!(mine.to_map().len(), 3);
+        assert_eq!(
+            mine.with_prefix("departure")
+                .collect::<Vec<(&str, usize)>>(),
+            vec![("departure row", 11), ("departure \"seat\", 2", 13)]
+        );
+        assert_eq!(part2(&ins), 143);
+        // The last nearby ticket is invalid
+        assert_eq!(nearby.len(), 3);
+        assert_eq!(
+            mine.to_json(),
+            r#"{"departure row":11,"class":12,"departure \"seat\", 2":13}"#
+        );
+        assert_eq!(
+            tickets_to_csv(&nearby[..2]),
+            "departure row,class,\"departure \"\"seat\"\", 2\"\n3,9,18\n15,1,5\n"
+        );
+        assert_eq!(tickets_to_csv(&[]), "");
+    }
+
+    fn instruction(rules: &[&str], nearby_tickets: Vec<Vec<usize>>) -> Instruction {
+        Instruction {
+            rules: rules.iter().map(|r| r.parse().unwrap()).collect(),
+            my_ticket: nearby_tickets[0].clone(),
+            nearby_tickets,
+        }
+    }
+
+    #[test]
+    fn test_all_assignments() {
+        let ins = instruction(
+            &["a: 0-5", "b: 0-5", "c: 0-10"],
+            vec![vec![1, 2, 9], vec![3, 4, 10]],
+        );
+        assert_eq!(
+            all_assignments(&ins.rules, &ins.nearby_tickets, 10),
+            Ok(vec![vec![0, 1, 2], vec![1, 0, 2]])
+        );
+        assert_eq!(
+            all_assignments(&ins.rules, &ins.nearby_tickets, 1),
+            Ok(vec![vec![0, 1, 2]])
+        );
+        assert_eq!(
+            map_columns(&ins.rules, &ins.nearby_tickets),
+            Err(AssignmentError::Ambiguous {
+                columns: vec![0, 1]
+            })
+        );
+    }
+
+    #[test]
+    fn test_symmetric_assignments() {
+        // 50! assignments, far too many to enumerate
+        let rules: Vec<String> = (0..50).map(|i| format!("f{}: 0-100", i)).collect();
+        let rules: Vec<&str> = rules.iter().map(String::as_str).collect();
+        let ins = instruction(&rules, vec![(0..50).collect(), vec![100; 50]]);
+        assert_eq!(
+            map_columns(&ins.rules, &ins.nearby_tickets),
+            Err(AssignmentError::Ambiguous {
+                columns: (0..50).collect()
+            })
+        );
+        assert_eq!(
+            all_assignments(&ins.rules, &ins.nearby_tickets, 3)
+                .unwrap()
+                .len(),
+            3
+        );
+
+        // The same size with a rule of its own for every column
+        let rules: Vec<String> = (0..50)
+            .map(|i| format!("f{}: {}-{} or 1000-1000", i, i, i))
+            .collect();
+        let rules: Vec<&str> = rules.iter().map(String::as_str).collect();
+        let ins = instruction(&rules, vec![(0..50).collect(), vec![1000; 50]]);
+        let names = map_columns(&ins.rules, &ins.nearby_tickets).unwrap();
+        assert_eq!(names[0], "f0");
+        assert_eq!(names[49], "f49");
+    }
+
+    #[test]
+    fn test_unsatisfiable() {
+        // Columns 0 and 2 both only fit rule a
+        let ins = instruction(
+            &["a: 0-5", "b: 6-10", "c: 11-20"],
+            vec![vec![1, 9, 2], vec![3, 7, 4]],
+        );
+        let err = map_columns(&ins.rules, &ins.nearby_tickets).unwrap_err();
+        assert_eq!(
+            err,
+            AssignmentError::Unsatisfiable {
+                columns: vec![0, 2]
+            }
+        );
+        assert_eq!(err.to_string(), "not enough fields fit columns [0, 2]");
+
+        // No rule fits column 1
+        let ins = instruction(&["a: 0-5", "b: 0-5"], vec![vec![1, 9]]);
+        assert_eq!(
+            all_assignments(&ins.rules, &ins.nearby_tickets, 10),
+            Err(AssignmentError::Unsatisfiable { columns: vec![1] })
+        );
+    }
+
+    #[test]
+    fn test_invalid_ticket_width() {
+        let ins = instruction(&["a: 0-5", "b: 0-5"], vec![vec![1, 2]]);
+        let err = map_columns(&ins.rules, &[vec![1, 2], vec![1]]).unwrap_err();
+        assert_eq!(
+            err,
+            AssignmentError::InvalidTicketWidth {
+                ticket: 1,
+                found: 1,
+                expected: 2
+            }
+        );
+        assert_eq!(err.to_string(), "ticket 1 has 1 values, expected 2");
+        assert_eq!(
+            all_assignments(&ins.rules, &[vec![1, 2, 3]], 10),
+            Err(AssignmentError::InvalidTicketWidth {
+                ticket: 0,
+                found: 3,
+                expected: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_rule() {
+        let rule = Rule::new("a", vec![(10, 12), (1, 3), (2, 5), (6, 7), (20, 20)]);
+        assert_eq!(rule.ranges, vec![(1, 7), (10, 12), (20, 20)]);
+        let valid: Vec<usize> = (0..25).filter(|&v| rule.contains(v)).collect();
+        assert_eq!(valid, vec![1, 2, 3, 4, 5, 6, 7, 10, 11, 12, 20]);
+
+        let rule = Rule::new("b", vec![(5, usize::MAX), (0, 3)]);
+        assert!(rule.contains(usize::MAX));
+        assert!(!rule.contains(4));
+        assert!(!Rule::new("c", vec![]).contains(0));
+    }
+
+    #[test]
+    fn test_rule_parser() {
+        assert_eq!(
+            "departure location: 1-3".parse(),
+            Ok(Rule::new("departure location", vec![(1, 3)]))
+        );
+        assert_eq!(
+            "zone: 1-3 or 5-7 or 4-4 or 10-11".parse(),
+            Ok(Rule::new("zone", vec![(1, 7), (10, 11)]))
+        );
+        assert_eq!(
+            "zone: 1-3 or 7-5".parse::<Rule>(),
+            Err(ParseError::InvalidRange("7-5".to_string()))
+        );
+        assert_eq!(
+            "zone: 1-3 or".parse::<Rule>(),
+            Err(ParseError::InvalidRange("1-3 or".to_string()))
+        );
+        assert_eq!(
+            "1-3 or 5-7".parse::<Rule>(),
+            Err(ParseError::MissingName("1-3 or 5-7".to_string()))
+        );
+    }
 }
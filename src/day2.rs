@@ -1,8 +1,13 @@
+use regex::Regex;
+use std::fmt;
+
+/// A line of the password database, `1-3 a: abcde`. How the numbers and the
+/// letter are used is up to the policy.
 #[derive(Debug)]
 pub struct Password {
-    min: usize,
-    max: usize,
-    policy: char,
+    first: usize,
+    second: usize,
+    letter: char,
     password: String,
 }
 
@@ -11,34 +16,167 @@ impl From<&str> for Password {
         let parts = data.trim().split(' ').collect::<Vec<&str>>();
         let mut range = parts[0].split('-').map(|x| x.parse().unwrap());
         Password {
-            min: range.next().unwrap(),
-            max: range.next().unwrap(),
-            policy: parts[1].chars().next().unwrap(),
+            first: range.next().unwrap(),
+            second: range.next().unwrap(),
+            letter: parts[1].chars().next().unwrap(),
             password: parts[2].to_string(),
         }
     }
 }
 
 impl Password {
-    fn is_within_range(&self) -> bool {
-        let count = self.password.chars().filter(|c| c == &self.policy).count();
-        count >= self.min && count <= self.max
+    pub fn first(&self) -> usize {
+        self.first
+    }
+
+    pub fn second(&self) -> usize {
+        self.second
+    }
+
+    pub fn letter(&self) -> char {
+        self.letter
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+pub trait PasswordPolicy {
+    fn is_valid(&self, password: &Password) -> bool;
+}
+
+/// The letter occurs between `first` and `second` times, inclusive
+pub struct LetterCount;
+
+impl PasswordPolicy for LetterCount {
+    fn is_valid(&self, p: &Password) -> bool {
+        let count = p.password.chars().filter(|c| c == &p.letter).count();
+        count >= p.first && count <= p.second
+    }
+}
+
+/// The letter is at exactly one of the 1-based positions `first` and `second`
+pub struct LetterPosition;
+
+impl PasswordPolicy for LetterPosition {
+    fn is_valid(&self, p: &Password) -> bool {
+        let at = |position: usize| {
+            position
+                .checked_sub(1)
+                .and_then(|i| p.password.chars().nth(i))
+                == Some(p.letter)
+        };
+        at(p.first) != at(p.second)
+    }
+}
+
+/// The password matches a regular expression, anywhere unless anchored
+pub struct Matches(pub Regex);
+
+impl PasswordPolicy for Matches {
+    fn is_valid(&self, p: &Password) -> bool {
+        self.0.is_match(&p.password)
+    }
+}
+
+/// Minimum number of characters of each class
+#[derive(Clone, Debug, Default)]
+pub struct CharacterClasses {
+    pub lowercase: usize,
+    pub uppercase: usize,
+    pub digits: usize,
+    /// Anything that isn't an ASCII letter or digit
+    pub symbols: usize,
+}
+
+impl PasswordPolicy for CharacterClasses {
+    fn is_valid(&self, p: &Password) -> bool {
+        let count = |f: fn(&char) -> bool| p.password.chars().filter(f).count();
+        count(char::is_ascii_lowercase) >= self.lowercase
+            && count(char::is_ascii_uppercase) >= self.uppercase
+            && count(char::is_ascii_digit) >= self.digits
+            && count(|c| !c.is_ascii_alphanumeric()) >= self.symbols
     }
+}
+
+/// The password contains none of the substrings
+pub struct ForbiddenSubstrings(pub Vec<String>);
 
-    fn only_one(&self) -> bool {
-        let chars: Vec<char> = self.password.chars().collect();
-        let first = chars[self.min - 1] == self.policy;
-        let second = chars[self.max - 1] == self.policy;
-        (first && !second) || (!first && second)
+impl PasswordPolicy for ForbiddenSubstrings {
+    fn is_valid(&self, p: &Password) -> bool {
+        !self.0.iter().any(|s| p.password.contains(s.as_str()))
     }
+}
 
-    fn only_one_2(&self) -> bool {
-        let first = self.password.chars().nth(self.min - 1).unwrap() == self.policy;
-        let second = self.password.chars().nth(self.max - 1).unwrap() == self.policy;
-        (first && !second) || (!first && second)
+#[derive(Clone, Debug, PartialEq)]
+pub enum PolicyError {
+    UnknownPolicy(String),
+    InvalidArgument(String),
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownPolicy(name) => write!(f, "unknown password policy {:?}", name),
+            Self::InvalidArgument(message) => write!(f, "invalid policy argument: {}", message),
+        }
     }
 }
 
+/// Builds a policy from its name, with the argument after a colon where the
+/// policy needs one:
+///
+/// - `letter-count` and `letter-position`
+/// - `matches:<regex>`
+/// - `classes:<class>=<n>,...` with the classes `lowercase`, `uppercase`,
+///   `digits` and `symbols`
+/// - `forbidden:<substring>,...`
+pub fn policy_by_name(spec: &str) -> Result<Box<dyn PasswordPolicy>, PolicyError> {
+    let (name, argument) = match spec.find(':') {
+        Some(i) => (&spec[..i], Some(&spec[i + 1..])),
+        None => (spec, None),
+    };
+    let argument = || {
+        argument.ok_or_else(|| PolicyError::InvalidArgument(format!("{} needs an argument", name)))
+    };
+    Ok(match name {
+        "letter-count" => Box::new(LetterCount),
+        "letter-position" => Box::new(LetterPosition),
+        "matches" => Box::new(Matches(
+            Regex::new(argument()?).map_err(|e| PolicyError::InvalidArgument(e.to_string()))?,
+        )),
+        "classes" => {
+            let mut classes = CharacterClasses::default();
+            for minimum in argument()?.split(',') {
+                let invalid = || PolicyError::InvalidArgument(minimum.to_string());
+                let mut parts = minimum.splitn(2, '=');
+                let class = match parts.next() {
+                    Some("lowercase") => &mut classes.lowercase,
+                    Some("uppercase") => &mut classes.uppercase,
+                    Some("digits") => &mut classes.digits,
+                    Some("symbols") => &mut classes.symbols,
+                    _ => return Err(invalid()),
+                };
+                *class = parts
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(invalid)?;
+            }
+            Box::new(classes)
+        }
+        "forbidden" => Box::new(ForbiddenSubstrings(
+            argument()?.split(',').map(str::to_string).collect(),
+        )),
+        _ => return Err(PolicyError::UnknownPolicy(name.to_string())),
+    })
+}
+
+/// Number of passwords valid under `policy`
+pub fn count_valid<P: PasswordPolicy + ?Sized>(input: &[Password], policy: &P) -> usize {
+    input.iter().filter(|p| policy.is_valid(p)).count()
+}
+
 #[aoc_generator(day2)]
 pub fn input_generator(input: &str) -> Vec<Password> {
     input.lines().map(Password::from).collect()
@@ -46,15 +184,121 @@ pub fn input_generator(input: &str) -> Vec<Password> {
 
 #[aoc(day2, part1)]
 pub fn part1(input: &[Password]) -> usize {
-    input.iter().filter(|p| p.is_within_range()).count()
+    count_valid(input, &LetterCount)
 }
 
 #[aoc(day2, part2, collected)]
 pub fn part2(input: &[Password]) -> usize {
-    input.iter().filter(|p| p.only_one()).count()
+    count_valid(input, &LetterPosition)
 }
 
 #[aoc(day2, part2, iter)]
 pub fn part2_iter(input: &[Password]) -> usize {
-    input.iter().filter(|p| p.only_one_2()).count()
+    count_valid(input, &LetterPosition)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SAMPLE_INPUT: &'static str = "1-3 a: abcde
+1-3 b: cdefg
+2-9 c: ccccccccc";
+
+    #[test]
+    fn test_part1() {
+        let input = input_generator(SAMPLE_INPUT);
+        assert_eq!(input[1].letter(), 'b');
+        assert_eq!((input[2].first(), input[2].second()), (2, 9));
+        assert_eq!(part1(&input), 2);
+    }
+
+    #[test]
+    fn test_part2() {
+        let input = input_generator(SAMPLE_INPUT);
+        assert_eq!(part2(&input), 1);
+        assert_eq!(part2_iter(&input), 1);
+        // Positions past the end never hold the letter
+        let input = input_generator("1-30 a: abc\n0-1 a: abc");
+        assert_eq!(part2(&input), 2);
+    }
+
+    #[test]
+    fn test_policies() {
+        let input = input_generator("1-3 n: Hunter2!\n1-3 b: hunter\n1-3 c: P4ssword");
+        let valid = |policy: &dyn PasswordPolicy| -> Vec<&str> {
+            input
+                .iter()
+                .filter(|p| policy.is_valid(p))
+                .map(|p| p.password())
+                .collect()
+        };
+
+        let pattern = Matches("^[A-Z]\\w+\\d".parse().unwrap());
+        assert_eq!(valid(&pattern), vec!["Hunter2!"]);
+        // Runs in linear time, a backtracking matcher would take ages
+        let pattern = Matches(Regex::new("^a*a*a*a*a*a*a*b$").unwrap());
+        let long = input_generator(&format!("1-1 a: {}", "a".repeat(100)));
+        assert_eq!(count_valid(&long, &pattern), 0);
+
+        let classes = CharacterClasses {
+            uppercase: 1,
+            digits: 1,
+            ..CharacterClasses::default()
+        };
+        assert_eq!(valid(&classes), vec!["Hunter2!", "P4ssword"]);
+        let classes = CharacterClasses {
+            symbols: 1,
+            ..classes
+        };
+        assert_eq!(valid(&classes), vec!["Hunter2!"]);
+
+        let forbidden = ForbiddenSubstrings(vec!["unter".to_string(), "ss".to_string()]);
+        assert!(valid(&forbidden).is_empty());
+        let policies: Vec<Box<dyn PasswordPolicy>> = vec![
+            Box::new(ForbiddenSubstrings(vec!["word".to_string()])),
+            Box::new(LetterCount),
+        ];
+        let counts: Vec<usize> = policies
+            .iter()
+            .map(|p| count_valid(&input, p.as_ref()))
+            .collect();
+        assert_eq!(counts, vec![2, 1]);
+    }
+
+    #[test]
+    fn test_policy_by_name() {
+        let input = input_generator("1-3 n: Hunter2!\n1-3 b: hunter\n1-3 c: P4ssword");
+        let count = |spec: &str| count_valid(&input, policy_by_name(spec).unwrap().as_ref());
+        assert_eq!(count("letter-count"), 1);
+        assert_eq!(count("letter-position"), 1);
+        assert_eq!(count("matches:^[A-Z]"), 2);
+        assert_eq!(count("classes:uppercase=1,digits=1"), 2);
+        assert_eq!(count("classes:symbols=1"), 1);
+        assert_eq!(count("forbidden:word,2"), 1);
+        // Only the first colon separates the argument
+        assert_eq!(count("matches:^[^:]*$"), 3);
+
+        let error = |spec: &str| policy_by_name(spec).err().unwrap();
+        assert_eq!(
+            error("length"),
+            PolicyError::UnknownPolicy("length".to_string())
+        );
+        assert_eq!(
+            error("matches").to_string(),
+            "invalid policy argument: matches needs an argument"
+        );
+        assert_eq!(
+            error("classes:digits=two"),
+            PolicyError::InvalidArgument("digits=two".to_string())
+        );
+        assert_eq!(
+            error("classes:spaces=1"),
+            PolicyError::InvalidArgument("spaces=1".to_string())
+        );
+        assert!(matches!(
+            error("matches:("),
+            PolicyError::InvalidArgument(_)
+        ));
+    }
 }
@@ -1,4 +1,5 @@
-use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
 
 #[aoc_generator(day5)]
 pub fn input_generator(input: &str) -> Vec<(String, String)> {
@@ -17,32 +18,335 @@ pub fn input_generator_lines(input: &str) -> Vec<String> {
     input.lines().map(|x| x.to_string()).collect()
 }
 
-fn scanner(range: &mut (u32, u32), ch: char) -> Option<(u32, u32)> {
-    *range = bsp(ch, *range);
-    Some(*range)
-}
-
 fn generate_seats(input: &[(String, String)]) -> Vec<(u32, u32)> {
+    let decoder = Decoder::default();
     input
         .iter()
         .map(|(row_input, col_input)| {
-            (
-                row_input.chars().scan((0, 127), scanner).last().unwrap().0,
-                col_input.chars().scan((0, 7), scanner).last().unwrap().0,
-            )
+            decoder
+                .decode(&[row_input.as_str(), col_input].concat())
+                .unwrap()
         })
         .collect()
 }
 
 fn calculate_seat_id(data: &str) -> u32 {
-    let mut id = 0u32;
-    for (i, v) in data.chars().enumerate() {
-        if v == 'B' || v == 'R' {
-            id |= 1 << (9 - i)
+    Decoder::default().seat_id(data).unwrap()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PassError {
+    /// Row/column counts must be non-zero and each letter pair distinct
+    InvalidLayout,
+    InvalidLength {
+        expected: usize,
+        found: usize,
+    },
+    InvalidCharacter {
+        position: usize,
+        found: char,
+    },
+    /// The pass (or seat) points outside of the plane
+    OutOfRange {
+        position: usize,
+    },
+}
+
+/// Binary space partitioning decoder for boarding passes.
+///
+/// A pass consists of the row characters followed by the column characters.
+/// Each character selects the lower or upper half of the remaining range, the
+/// letters used for this are configurable per axis as `(lower, upper)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decoder {
+    rows: u32,
+    columns: u32,
+    row_letters: (char, char),
+    column_letters: (char, char),
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder {
+            rows: 128,
+            columns: 8,
+            row_letters: ('F', 'B'),
+            column_letters: ('L', 'R'),
+        }
+    }
+}
+
+impl Decoder {
+    pub fn new(
+        rows: u32,
+        columns: u32,
+        row_letters: (char, char),
+        column_letters: (char, char),
+    ) -> Result<Self, PassError> {
+        if rows == 0
+            || columns == 0
+            || rows.checked_mul(columns).is_none()
+            || row_letters.0 == row_letters.1
+            || column_letters.0 == column_letters.1
+        {
+            return Err(PassError::InvalidLayout);
+        }
+        Ok(Decoder {
+            rows,
+            columns,
+            row_letters,
+            column_letters,
+        })
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    /// Number of characters needed to encode the row
+    pub fn row_length(&self) -> usize {
+        partition_length(self.rows)
+    }
+
+    /// Number of characters needed to encode the column
+    pub fn column_length(&self) -> usize {
+        partition_length(self.columns)
+    }
+
+    /// Decodes a pass into its `(row, column)`
+    pub fn decode(&self, pass: &str) -> Result<(u32, u32), PassError> {
+        let chars: Vec<char> = pass.chars().collect();
+        let expected = self.row_length() + self.column_length();
+        if chars.len() != expected {
+            return Err(PassError::InvalidLength {
+                expected,
+                found: chars.len(),
+            });
+        }
+        let (row_chars, col_chars) = chars.split_at(self.row_length());
+        Ok((
+            partition(row_chars, 0, self.rows, self.row_letters)?,
+            partition(
+                col_chars,
+                row_chars.len(),
+                self.columns,
+                self.column_letters,
+            )?,
+        ))
+    }
+
+    pub fn seat_id(&self, pass: &str) -> Result<u32, PassError> {
+        let (row, col) = self.decode(pass)?;
+        Ok(row * self.columns + col)
+    }
+
+    /// Encodes a `(row, column)` back into a pass
+    pub fn encode(&self, (row, col): (u32, u32)) -> Result<String, PassError> {
+        if row >= self.rows {
+            return Err(PassError::OutOfRange { position: 0 });
+        }
+        if col >= self.columns {
+            return Err(PassError::OutOfRange {
+                position: self.row_length(),
+            });
+        }
+        let mut pass = String::with_capacity(self.row_length() + self.column_length());
+        encode_partition(&mut pass, row, self.rows, self.row_letters);
+        encode_partition(&mut pass, col, self.columns, self.column_letters);
+        Ok(pass)
+    }
+
+    /// Encodes a seat ID (`row * columns + column`) back into a pass
+    pub fn encode_seat_id(&self, id: u32) -> Result<String, PassError> {
+        self.encode((id / self.columns, id % self.columns))
+    }
+}
+
+// Smallest number of halvings needed to narrow `size` down to a single value
+fn partition_length(size: u32) -> usize {
+    (32 - (size - 1).leading_zeros()) as usize
+}
+
+fn partition(
+    chars: &[char],
+    offset: usize,
+    size: u32,
+    (lower, upper): (char, char),
+) -> Result<u32, PassError> {
+    let mut range = (0, size - 1);
+    for (i, &ch) in chars.iter().enumerate() {
+        let position = offset + i;
+        range = match ch {
+            c if c == lower => halve(range, false),
+            c if c == upper => halve(range, true),
+            found => return Err(PassError::InvalidCharacter { position, found }),
+        };
+        // Only possible for sizes that aren't a power of two
+        if range.0 > range.1 {
+            return Err(PassError::OutOfRange { position });
         }
     }
+    Ok(range.0)
+}
 
-    id
+fn encode_partition(pass: &mut String, value: u32, size: u32, (lower, upper): (char, char)) {
+    let mut range = (0, size - 1);
+    for _ in 0..partition_length(size) {
+        let upper_half = value > midpoint(range);
+        pass.push(if upper_half { upper } else { lower });
+        range = halve(range, upper_half);
+    }
+}
+
+// Last value of the lower half, without overflowing on large layouts
+fn midpoint(range: (u32, u32)) -> u32 {
+    range.0 + (range.1 - range.0) / 2
+}
+
+fn halve(range: (u32, u32), upper: bool) -> (u32, u32) {
+    let delta = midpoint(range);
+    if upper {
+        (delta + 1, range.1)
+    } else {
+        (range.0, delta)
+    }
+}
+
+/// Occupancy of every seat on the plane, as recorded from boarding passes
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeatMap {
+    rows: u32,
+    columns: u32,
+    seats: Vec<bool>,
+    duplicates: Vec<(u32, u32)>,
+}
+
+impl SeatMap {
+    pub fn new(rows: u32, columns: u32) -> Self {
+        SeatMap {
+            rows,
+            columns,
+            seats: vec![false; (rows * columns) as usize],
+            duplicates: Vec::new(),
+        }
+    }
+
+    /// Decodes and records every pass using the layout of `decoder`
+    pub fn from_passes<I, S>(decoder: &Decoder, passes: I) -> Result<Self, PassError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut map = SeatMap::new(decoder.rows(), decoder.columns());
+        for pass in passes {
+            map.insert(decoder.decode(pass.as_ref())?);
+        }
+        Ok(map)
+    }
+
+    /// Marks a seat as occupied. Returns false (and records the seat as a
+    /// duplicate) if it was already occupied.
+    pub fn insert(&mut self, (row, col): (u32, u32)) -> bool {
+        let index = self.index((row, col));
+        if self.seats[index] {
+            self.duplicates.push((row, col));
+            return false;
+        }
+        self.seats[index] = true;
+        true
+    }
+
+    pub fn is_occupied(&self, seat: (u32, u32)) -> bool {
+        self.seats[self.index(seat)]
+    }
+
+    /// Seats that had more than one pass, once per extra pass
+    pub fn duplicates(&self) -> &[(u32, u32)] {
+        &self.duplicates
+    }
+
+    pub fn occupied_count(&self) -> usize {
+        self.seats.iter().filter(|&&s| s).count()
+    }
+
+    /// Rows at the front of the plane without a single occupied seat
+    pub fn missing_front_rows(&self) -> Range<u32> {
+        0..(0..self.rows)
+            .find(|&r| !self.is_row_empty(r))
+            .unwrap_or(self.rows)
+    }
+
+    /// Rows at the back of the plane without a single occupied seat
+    pub fn missing_back_rows(&self) -> Range<u32> {
+        let start = (0..self.rows)
+            .rev()
+            .find(|&r| !self.is_row_empty(r))
+            .map_or(self.rows, |r| r + 1);
+        start.max(self.missing_front_rows().end)..self.rows
+    }
+
+    /// All empty seats, except for the ones in missing front/back rows
+    pub fn empty_seats(&self) -> Vec<(u32, u32)> {
+        let front = self.missing_front_rows();
+        let back = self.missing_back_rows();
+        (front.end..back.start)
+            .flat_map(|r| (0..self.columns).map(move |c| (r, c)))
+            .filter(|&seat| !self.is_occupied(seat))
+            .collect()
+    }
+
+    /// Empty seats with both neighbouring seat IDs occupied, see
+    /// `missing_seats`
+    pub fn missing_seats(&self) -> Vec<(u32, u32)> {
+        let occupied = (0..self.seats.len() as u32).filter(|&i| self.seats[i as usize]);
+        missing_seats(occupied)
+            .into_iter()
+            .map(|i| (i / self.columns, i % self.columns))
+            .collect()
+    }
+
+    fn is_row_empty(&self, row: u32) -> bool {
+        (0..self.columns).all(|c| !self.is_occupied((row, c)))
+    }
+
+    fn index(&self, (row, col): (u32, u32)) -> usize {
+        assert!(
+            row < self.rows && col < self.columns,
+            "seat ({}, {}) outside of plane",
+            row,
+            col
+        );
+        (row * self.columns + col) as usize
+    }
+}
+
+/// Renders the seating chart, one line per row. Occupied seats are drawn as
+/// `#`, empty seats as `.` and seats in missing front/back rows as `-`.
+impl fmt::Display for SeatMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let front = self.missing_front_rows();
+        let back = self.missing_back_rows();
+        let width = self.rows.saturating_sub(1).to_string().len();
+        for row in 0..self.rows {
+            write!(f, "{:>width$} ", row, width = width)?;
+            for col in 0..self.columns {
+                let ch = if front.contains(&row) || back.contains(&row) {
+                    '-'
+                } else if self.is_occupied((row, col)) {
+                    '#'
+                } else {
+                    '.'
+                };
+                write!(f, "{}", ch)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
 }
 
 #[aoc(day5, part1)]
@@ -73,95 +377,56 @@ pub fn part1_bit_operation(input: &[String]) -> u32 {
 
 #[aoc(day5, part2)]
 pub fn part2(input: &[(String, String)]) -> u32 {
-    let mut seats: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
-    let data = generate_seats(input);
-    for seat in data {
-        if let Some(row) = seats.get_mut(&seat.0) {
-            row.push(seat.1);
-        } else {
-            seats.insert(seat.0, vec![seat.1]);
-        }
-    }
-    let max_row = seats
-        .iter()
-        .max_by(|(r1, _), (r2, _)| r1.cmp(r2))
-        .unwrap()
-        .0
-        - 1;
-    let my_seat = seats
-        .iter()
-        .filter(|(r, c)| **r > 0 && **r < max_row && c.len() < 8)
-        .next()
-        .unwrap();
-    // Each row's sum equals 28. Since we filter on vector length above we can
-    // be sure that the calculation ends up correct, even if we have the first
-    // seat (0)
-    my_seat.0 * 8 + (28 - my_seat.1.iter().sum::<u32>())
+    let ids = generate_seats(input)
+        .into_iter()
+        .map(|(row, col)| row * 8 + col);
+    missing_seats(ids)[0]
 }
 
 #[aoc(day5, part2, bit_operation)]
 pub fn part2_bit_operation(input: &[String]) -> u32 {
-    let mut seat_ids: Vec<u32> = input.iter().map(|x| calculate_seat_id(x)).collect();
-    seat_ids.sort_unstable();
-    let min = 0;
-    let max = (seat_ids.last().unwrap() >> 3) - 1;
-
-    seat_ids
-        .iter()
-        .filter(|id| (**id >> 3) > min && (**id >> 3) < max)
-        .scan(0u32, |st, id| {
-            if *st == 0 {
-                *st = *id;
-                return Some(id + 1);
-            }
-            // We've found the gap, end the scan
-            if id - *st > 1 {
-                return None;
-            }
-            *st = *id;
-            Some(id + 1)
-        })
-        .last()
-        .unwrap()
+    missing_seats(input.iter().map(|x| calculate_seat_id(x)))[0]
 }
 
-pub fn bsp(section: char, range: (u32, u32)) -> (u32, u32) {
-    let delta = (range.1 + range.0) / 2;
-    match section {
-        'F' | 'L' => (range.0, delta),
-        'B' | 'R' => (delta + 1, range.1),
-        _ => unreachable!(),
+/// Finds every seat ID absent from `ids` where both neighbours (ID - 1 and
+/// ID + 1) are present, sorted ascending.
+///
+/// The IDs are collected into a bitset so order, duplicates and gaps in the
+/// input don't matter. Memory usage is proportional to the distance between
+/// the lowest and the highest ID.
+pub fn missing_seats<I: IntoIterator<Item = u32>>(ids: I) -> Vec<u32> {
+    let ids: Vec<u32> = ids.into_iter().collect();
+    let base = match ids.iter().min() {
+        Some(min) => min - min % 64,
+        None => return Vec::new(),
+    };
+    let mut bits: Vec<u64> = Vec::new();
+    for id in ids {
+        let word = ((id - base) / 64) as usize;
+        if word >= bits.len() {
+            bits.resize(word + 1, 0);
+        }
+        bits[word] |= 1 << (id % 64);
     }
+
+    let mut seats = Vec::new();
+    for (i, &word) in bits.iter().enumerate() {
+        // Carry the neighbouring bits over the word boundaries
+        let prev = if i > 0 { bits[i - 1] >> 63 } else { 0 };
+        let next = bits.get(i + 1).map_or(0, |w| w & 1);
+        let mut candidates = !word & (word << 1 | prev) & (word >> 1 | next << 63);
+        while candidates != 0 {
+            seats.push(base + i as u32 * 64 + candidates.trailing_zeros());
+            candidates &= candidates - 1;
+        }
+    }
+    seats
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    #[test]
-    fn test_scanner() {
-        let input = "FBFBBFF";
-        let mut r = input.chars().scan((0, 127), scanner);
-        assert_eq!(r.next(), Some((0, 63)));
-        assert_eq!(r.next(), Some((32, 63)));
-        assert_eq!(r.next(), Some((32, 47)));
-        assert_eq!(r.next(), Some((40, 47)));
-        assert_eq!(r.next(), Some((44, 47)));
-        assert_eq!(r.next(), Some((44, 45)));
-        assert_eq!(r.next(), Some((44, 44)));
-
-        let input = "BFFFBBF";
-        let r = input.chars().scan((0, 127), scanner).last();
-        assert_eq!(r, Some((70, 70)));
-
-        let input = "FFFBBBF";
-        let r = input.chars().scan((0, 127), scanner).last();
-        assert_eq!(r, Some((14, 14)));
-
-        let input = "BBFFBBF";
-        let r = input.chars().scan((0, 127), scanner).last();
-        assert_eq!(r, Some((102, 102)));
-    }
+    use proptest::prelude::*;
 
     #[test]
     fn test_bits_part1() {
@@ -193,4 +458,199 @@ mod tests {
         assert_eq!(part1_replace_bits(&vec![(row, col)]), 820);
         assert_eq!(part1_bit_operation(&vec![full]), 820);
     }
+
+    #[test]
+    fn test_decoder_default() {
+        let decoder = Decoder::default();
+        assert_eq!(decoder.decode("FBFBBFFRLR"), Ok((44, 5)));
+        assert_eq!(decoder.decode("BFFFBBFRRR"), Ok((70, 7)));
+        assert_eq!(decoder.decode("FFFBBBFRRR"), Ok((14, 7)));
+        assert_eq!(decoder.decode("BBFFBBFRLL"), Ok((102, 4)));
+        assert_eq!(decoder.seat_id("BBFFBBFRLL"), Ok(820));
+        assert_eq!(decoder.encode((44, 5)), Ok("FBFBBFFRLR".to_string()));
+        assert_eq!(decoder.encode_seat_id(820), Ok("BBFFBBFRLL".to_string()));
+        for id in 0..1024 {
+            let pass = decoder.encode_seat_id(id).unwrap();
+            assert_eq!(decoder.seat_id(&pass), Ok(id));
+        }
+    }
+
+    #[test]
+    fn test_decoder_custom_layout() {
+        let decoder = Decoder::new(3, 5, ('U', 'D'), ('<', '>')).unwrap();
+        assert_eq!(decoder.row_length(), 2);
+        assert_eq!(decoder.column_length(), 3);
+        for row in 0..3 {
+            for col in 0..5 {
+                let pass = decoder.encode((row, col)).unwrap();
+                assert_eq!(decoder.decode(&pass), Ok((row, col)));
+            }
+        }
+        assert_eq!(decoder.encode((2, 4)), Ok("DU>><".to_string()));
+        // Upper half of an already narrowed down range
+        assert_eq!(
+            decoder.decode("DD<<<"),
+            Err(PassError::OutOfRange { position: 1 })
+        );
+        assert_eq!(
+            decoder.encode((3, 0)),
+            Err(PassError::OutOfRange { position: 0 })
+        );
+        assert_eq!(
+            Decoder::new(0, 5, ('U', 'D'), ('<', '>')),
+            Err(PassError::InvalidLayout)
+        );
+        assert_eq!(
+            Decoder::new(3, 5, ('U', 'U'), ('<', '>')),
+            Err(PassError::InvalidLayout)
+        );
+    }
+
+    #[test]
+    fn test_decoder_large_layout() {
+        let decoder = Decoder::new(u32::MAX, 1, ('F', 'B'), ('L', 'R')).unwrap();
+        assert_eq!(decoder.row_length(), 32);
+        let last = format!("{}F", "B".repeat(31));
+        assert_eq!(decoder.decode(&last), Ok((u32::MAX - 1, 0)));
+        assert_eq!(decoder.encode((u32::MAX - 1, 0)), Ok(last));
+        assert_eq!(
+            decoder.decode(&"B".repeat(32)),
+            Err(PassError::OutOfRange { position: 31 })
+        );
+        let pass = decoder.encode((1 << 31, 0)).unwrap();
+        assert_eq!(decoder.decode(&pass), Ok((1 << 31, 0)));
+    }
+
+    #[test]
+    fn test_decoder_validation() {
+        let decoder = Decoder::default();
+        assert_eq!(
+            decoder.decode("FBFBBFFRL"),
+            Err(PassError::InvalidLength {
+                expected: 10,
+                found: 9
+            })
+        );
+        assert_eq!(
+            decoder.decode("FBFBBFFRXR"),
+            Err(PassError::InvalidCharacter {
+                position: 8,
+                found: 'X'
+            })
+        );
+        // Column letters are not valid for rows
+        assert_eq!(
+            decoder.decode("LBFBBFFRLR"),
+            Err(PassError::InvalidCharacter {
+                position: 0,
+                found: 'L'
+            })
+        );
+    }
+
+    #[test]
+    fn test_seat_map() {
+        let decoder = Decoder::new(6, 4, ('F', 'B'), ('L', 'R')).unwrap();
+        let passes: Vec<String> = [5, 6, 8, 9, 10, 12, 13, 9]
+            .iter()
+            .map(|&id| decoder.encode_seat_id(id).unwrap())
+            .collect();
+        let seats = SeatMap::from_passes(&decoder, &passes).unwrap();
+        assert_eq!(seats.occupied_count(), 7);
+        assert_eq!(seats.duplicates(), &[(2, 1)]);
+        assert_eq!(seats.missing_front_rows(), 0..1);
+        assert_eq!(seats.missing_back_rows(), 4..6);
+        assert_eq!(
+            seats.empty_seats(),
+            vec![(1, 0), (1, 3), (2, 3), (3, 2), (3, 3)]
+        );
+        assert_eq!(seats.missing_seats(), vec![(1, 3), (2, 3)]);
+        assert_eq!(
+            seats.to_string(),
+            "0 ----\n1 .##.\n2 ###.\n3 ##..\n4 ----\n5 ----\n"
+        );
+
+        // Two empty seats next to each other aren't missing seats
+        let mut seats = SeatMap::new(1, 6);
+        for col in &[0, 3, 5] {
+            seats.insert((0, *col));
+        }
+        assert_eq!(seats.missing_seats(), vec![(0, 4)]);
+    }
+
+    #[test]
+    fn test_seat_map_empty() {
+        let seats = SeatMap::new(2, 2);
+        assert_eq!(seats.missing_front_rows(), 0..2);
+        assert_eq!(seats.missing_back_rows(), 2..2);
+        assert_eq!(seats.empty_seats(), vec![]);
+        assert_eq!(seats.missing_seats(), vec![]);
+    }
+
+    #[test]
+    fn test_missing_seats() {
+        assert_eq!(missing_seats(vec![]), vec![]);
+        assert_eq!(missing_seats(vec![0, 2]), vec![1]);
+        // Seat ID 0 has no front neighbour
+        assert_eq!(missing_seats(vec![1, 2, 3]), vec![]);
+        assert_eq!(missing_seats(vec![9, 3, 7, 5, 3, 5]), vec![4, 6, 8]);
+        // Over word boundaries
+        assert_eq!(missing_seats(vec![62, 64, 127, 129]), vec![63, 128]);
+        assert_eq!(
+            missing_seats(vec![u32::MAX - 2, u32::MAX]),
+            vec![u32::MAX - 1]
+        );
+    }
+
+    #[test]
+    fn test_part2() {
+        let decoder = Decoder::default();
+        let input: Vec<(String, String)> = (100..200)
+            .filter(|&id| id != 150)
+            .map(|id| {
+                let pass = decoder.encode_seat_id(id).unwrap();
+                let (row, col) = pass.split_at(7);
+                (row.to_string(), col.to_string())
+            })
+            .collect();
+        assert_eq!(part2(&input), 150);
+    }
+
+    #[test]
+    fn test_part2_bit_operation() {
+        let decoder = Decoder::default();
+        let input: Vec<String> = vec![8, 10, 9, 12, 8, 13, 11, 15]
+            .into_iter()
+            .map(|id| decoder.encode_seat_id(id).unwrap())
+            .collect();
+        assert_eq!(part2_bit_operation(&input), 14);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_missing_seats_matches_naive(ids in prop::collection::vec(0u32..2048, 0..512)) {
+            let set: std::collections::HashSet<u32> = ids.iter().cloned().collect();
+            let expected: Vec<u32> = (1..2048)
+                .filter(|id| !set.contains(id) && set.contains(&(id - 1)) && set.contains(&(id + 1)))
+                .collect();
+            prop_assert_eq!(missing_seats(ids), expected);
+        }
+
+        #[test]
+        fn prop_missing_seats_single_gap(
+            start in 0u32..1000,
+            len in 3u32..1000,
+            offset in 1u32..1000,
+            duplicates in prop::collection::vec(0u32..1000, 0..50),
+        ) {
+            let gap = start + 1 + offset % (len - 2);
+            let mut manifest: Vec<u32> = (start..start + len)
+                .chain(duplicates.iter().map(|d| start + d % len))
+                .filter(|&id| id != gap)
+                .collect();
+            // Shuffle deterministically from the generated input
+            manifest.sort_by_key(|id| id.wrapping_mul(2_654_435_761) ^ offset);
+            prop_assert_eq!(missing_seats(manifest), vec![gap]);
+        }
+    }
 }